@@ -1,9 +1,11 @@
 use crate::{Result, UklientError, CLIENT};
 use chrono::{Duration, Utc};
+use reqwest::{RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use theseus::prelude::Credentials;
-use tokio::time::interval;
+use tokio::time::{sleep, Instant};
+use tracing::warn;
 
 const CODE_URL: &str =
     "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode";
@@ -18,12 +20,34 @@ const PROFILE_URL: &str = "https://api.minecraftservices.com/minecraft/profile";
 
 const CLIENT_ID: &str = "89f4991d-b660-41c0-9ee4-affe27d47bce";
 const DEFAULT_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+pub const DEFAULT_SCOPES: &[&str] = &["XboxLive.signin", "offline_access"];
+
+const MAX_ATTEMPTS: u32 = 3;
+/// Credentials are refreshed when they expire in less than this many minutes
+const REFRESH_MARGIN_MINUTES: i64 = 10;
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+/// Added to the polling interval every time Microsoft asks to slow down
+const SLOW_DOWN_STEP: std::time::Duration = std::time::Duration::from_secs(5);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceCode {
     pub user_code: String,
     pub device_code: String,
     pub verification_uri: String,
+    /// How many seconds the code can be used for
+    #[serde(default = "default_code_expiry")]
+    pub expires_in: u64,
+    /// How many seconds to wait between polls for the token
+    #[serde(default = "default_poll_interval")]
+    pub interval: u64,
+}
+
+fn default_code_expiry() -> u64 {
+    900
+}
+
+fn default_poll_interval() -> u64 {
+    5
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,13 +90,15 @@ pub async fn get_device_code(scopes: Vec<&str>) -> Result<DeviceCode> {
     Ok(code)
 }
 
-pub async fn get_credentials(device_code: String) -> Result<Credentials> {
+pub async fn get_credentials(code: &DeviceCode) -> Result<Credentials> {
     let body = [
         ("client_id", CLIENT_ID),
-        ("code", device_code.as_str()),
+        ("code", code.device_code.as_str()),
         ("grant_type", DEFAULT_GRANT),
     ];
-    let mut interval = interval(std::time::Duration::from_secs(5));
+    let deadline =
+        Instant::now() + std::time::Duration::from_secs(code.expires_in);
+    let mut interval = std::time::Duration::from_secs(code.interval.max(1));
 
     let token = loop {
         let response = CLIENT.post(TOKEN_URL).form(&body).send().await?;
@@ -85,16 +111,22 @@ pub async fn get_credentials(device_code: String) -> Result<Credentials> {
             Err(_) => {
                 let json: Map<String, Value> = serde_json::from_str(&text)?;
 
-                if json
-                    .get("error")
-                    .and_then(|v| v.as_str())
-                    .filter(|&s| s == "authorization_pending")
-                    .is_some()
-                {
-                    interval.tick().await;
-                } else {
-                    return Err(UklientError::LoginError(text));
+                match json.get("error").and_then(|v| v.as_str()) {
+                    Some("authorization_pending") => {}
+                    Some("slow_down") => interval += SLOW_DOWN_STEP,
+                    Some("expired_token") => {
+                        return Err(UklientError::AuthExpired)
+                    }
+                    Some("authorization_declined") => {
+                        return Err(UklientError::AuthDeclined)
+                    }
+                    _ => return Err(UklientError::LoginError(text)),
+                }
+
+                if Instant::now() + interval >= deadline {
+                    return Err(UklientError::AuthExpired);
                 }
+                sleep(interval).await;
             }
         }
     };
@@ -117,17 +149,47 @@ async fn fetch_info(token: &str) -> Result<ProfileInfo> {
     let mojang_token = get_mojang_services_token(&xbox_token.token).await?;
     let yggdrasil_token = get_yggdrasil_token(&mojang_token).await?;
 
-    Ok(CLIENT
-        .get(PROFILE_URL)
-        .header(
+    let response = send_with_retry(|| {
+        CLIENT.get(PROFILE_URL).header(
             reqwest::header::AUTHORIZATION,
             format!("Bearer {}", yggdrasil_token.access_token),
         )
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?)
+    })
+    .await;
+
+    match response {
+        Ok(response) => Ok(response.json().await?),
+        // the account exists, but it doesn't own the game, or it got it
+        // through game pass and never created a profile
+        Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            Err(UklientError::NoMinecraftOwnership)
+        }
+        Err(e) => Err(UklientError::ProfileError(e)),
+    }
+}
+
+/// Sends the request made by `request`, retrying a few times when the
+/// request fails in a way that might not happen again
+async fn send_with_retry(
+    request: impl Fn() -> RequestBuilder,
+) -> reqwest::Result<Response> {
+    let mut attempt = 1;
+    loop {
+        match request().send().await.and_then(Response::error_for_status) {
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                warn!("Request failed, retrying: {e}");
+                sleep(RETRY_DELAY * attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error.status().map_or(false, |s| s.is_server_error())
 }
 
 async fn get_xbox_token(token: &str) -> Result<XboxTokenInfo> {
@@ -143,15 +205,15 @@ async fn get_xbox_token(token: &str) -> Result<XboxTokenInfo> {
     }}"#
     );
 
-    Ok(CLIENT
-        .post(XBOX_USER_AUTH_URL)
-        .header(reqwest::header::CONTENT_TYPE, "application/json")
-        .body(body)
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?)
+    Ok(send_with_retry(|| {
+        CLIENT
+            .post(XBOX_USER_AUTH_URL)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone())
+    })
+    .await?
+    .json()
+    .await?)
 }
 
 async fn get_mojang_services_token(token: &str) -> Result<XboxTokenInfo> {
@@ -166,15 +228,15 @@ async fn get_mojang_services_token(token: &str) -> Result<XboxTokenInfo> {
     }}"#
     );
 
-    Ok(CLIENT
-        .post(XBOX_AUTHORIZATION_URL)
-        .header(reqwest::header::CONTENT_TYPE, "application/json")
-        .body(body)
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?)
+    Ok(send_with_retry(|| {
+        CLIENT
+            .post(XBOX_AUTHORIZATION_URL)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone())
+    })
+    .await?
+    .json()
+    .await?)
 }
 
 async fn get_yggdrasil_token(token: &XboxTokenInfo) -> Result<YggdrasilToken> {
@@ -187,15 +249,20 @@ async fn get_yggdrasil_token(token: &XboxTokenInfo) -> Result<YggdrasilToken> {
         token.token
     );
 
-    Ok(CLIENT
-        .post(YGGDRASIL_URL)
-        .header(reqwest::header::CONTENT_TYPE, "application/json")
-        .body(body)
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?)
+    Ok(send_with_retry(|| {
+        CLIENT
+            .post(YGGDRASIL_URL)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone())
+    })
+    .await?
+    .json()
+    .await?)
+}
+
+/// Whether `creds` expire soon enough that they should be refreshed
+pub fn needs_refresh(creds: &Credentials) -> bool {
+    creds.expires - Utc::now() < Duration::minutes(REFRESH_MARGIN_MINUTES)
 }
 
 pub async fn refresh_credentials(creds: Credentials) -> Result<Credentials> {
@@ -205,12 +272,8 @@ pub async fn refresh_credentials(creds: Credentials) -> Result<Credentials> {
         ("grant_type", "refresh_token"),
     ];
 
-    let response = CLIENT
-        .post(TOKEN_URL)
-        .form(&body)
-        .send()
-        .await?
-        .error_for_status()?;
+    let response =
+        send_with_retry(|| CLIENT.post(TOKEN_URL).form(&body)).await?;
     let token: Token = response.json().await?;
 
     let expiration = Utc::now() + Duration::seconds(token.expires_in);
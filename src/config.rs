@@ -0,0 +1,544 @@
+use crate::java::SUPPORTED_JAVA_VERSIONS;
+use crate::network::NetworkConfig;
+use crate::paths;
+use crate::system;
+use crate::version::{LoaderVersionRange, MinecraftVersion};
+use crate::{progress_style, Result, UklientError, GAME_VERSION, STYLE_BYTE};
+use clap::ValueEnum;
+use console::Term;
+use ferinth::structures::version::VersionType;
+use ferinth::Ferinth;
+use indicatif::ProgressStyle;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+use url::Url;
+
+const DEFAULT_MAX_MEMORY: u32 = 4 * 1024;
+const MIN_MEMORY: u32 = 512;
+const DEFAULT_PROGRESS_TICK_MS: u64 = 100;
+const DEFAULT_OLD_GENERATIONS: usize = 3;
+const DEFAULT_MAX_MEMORY_FRACTION: f64 = 0.75;
+const DEFAULT_LAUNCH_GRACE_PERIOD_SECS: u64 = 3;
+/// Network settings older versions read from the top of the config
+const NETWORK_KEYS: &[&str] = &[
+    "modrinth_mirror",
+    "download_concurrency",
+    "download_retries",
+    "retry_delay_ms",
+    "retry_jitter",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Config {
+    /// Hardlink override files into the instance instead of copying them,
+    /// falling back to a copy when that isn't possible
+    pub link_overrides: bool,
+    /// Overrides the Java version used for some Minecraft versions. Keys are
+    /// either a full version (`1.19.3`), a series (`1.19`) or `*` to match
+    /// every version
+    pub java_versions: HashMap<String, u8>,
+    /// The name shown for the profile, instead of the modpack's name
+    pub profile_name: Option<String>,
+    /// Extra arguments passed to the JVM. Arguments from an instance's
+    /// `jvm_args.txt` are added after these, so they take precedence
+    pub java_arguments: Vec<String>,
+    /// The least stable kind of modpack version that can be installed
+    pub release_channel: Channel,
+    /// The maximum amount of memory given to the game, in megabytes. Defaults
+    /// to half of the machine's memory, between 2048 and 8192
+    pub max_memory: Option<u32>,
+    /// The size of the game window
+    pub resolution: Option<Resolution>,
+    /// Java binaries to use for some Java versions, instead of looking for
+    /// one or downloading it. Keys are Java major versions (`17`)
+    pub java_paths: HashMap<String, PathBuf>,
+    /// How often progress bars are redrawn, in milliseconds. 0 only redraws
+    /// them when they progress
+    pub progress_tick_ms: Option<u64>,
+    /// The template of the download progress bars, see indicatif's
+    /// documentation for what it can contain, e.g.
+    /// `{spinner} {percent}% {eta}`
+    pub progress_template: Option<String>,
+    /// The language the game is launched in (`en_us`, `fr_fr`...), instead
+    /// of the one chosen in game
+    pub language: Option<String>,
+    /// Check downloaded JDKs against the signature Adoptium publishes for
+    /// them, using `gpg`. Only Adoptium's release key
+    /// (`3B04D753C9050D9A5D343F39843C48A565F8F04B`) is trusted
+    pub verify_java_signature: bool,
+    /// Download early access JDKs when a Java version has no release yet
+    pub java_early_access: bool,
+    /// Whether to download a JRE, which is enough to play and about half the
+    /// size, or a full JDK. Defaults to `jre`
+    pub java_image_type: ImageType,
+    /// Mods that aren't part of the modpack, downloaded into the mods
+    /// directory. They are skipped if they can't be downloaded
+    pub extra_mods: Vec<ExtraMod>,
+    /// How many updates worth of replaced files are kept in `.old`
+    /// directories. Defaults to 3
+    pub old_generations: Option<usize>,
+    /// Never delete anything from `.old` directories after an update
+    pub keep_old: bool,
+    /// The key used to find where CurseForge modpack files are downloaded
+    /// from, only needed to install CurseForge modpacks
+    pub curseforge_api_key: Option<String>,
+    /// Use versions without a patch (`1.19`) as they are, instead of the
+    /// newest patch of that series the modpack supports
+    pub exact_game_version: bool,
+    /// The Minecraft version modpacks are installed for, instead of the
+    /// default one. A version without a patch (`1.19` or `1.19.x`) is the
+    /// newest patch of that series the modpack supports
+    pub game_version: Option<String>,
+    /// Where instances are installed, instead of the data directory
+    pub instances_dir: Option<PathBuf>,
+    /// Where modpacks are extracted while being installed, instead of the
+    /// cache directory. Pointing this at a RAM disk like `/dev/shm` makes
+    /// installs faster. Everything goes in a `uklient` directory inside it
+    pub tmp_dir: Option<PathBuf>,
+    /// A Modrinth personal access token, to install private modpacks
+    pub modrinth_token: Option<String>,
+    /// What to do when the game could use more memory than the machine can
+    /// spare
+    pub memory_check: MemoryCheck,
+    /// How much of the machine's memory the game can use before
+    /// `memory_check` kicks in. Defaults to 0.75
+    pub max_memory_fraction: Option<f64>,
+    /// The Fabric or Quilt loader version to use (`0.14.21`), or a range to
+    /// use the newest version in (`>=0.14, <0.15`), instead of the latest one
+    pub loader_version: Option<String>,
+    /// Modrinth project IDs of the mods that aren't installed with
+    /// `--low-end`, e.g. shader loaders or particle mods
+    pub low_end_excluded: Vec<String>,
+    /// How many seconds the game must keep running after being launched to
+    /// be considered started, a game that exits sooner has its error output
+    /// shown. Slow machines may need more for failures to start to be
+    /// caught, but longer periods also report crashes that have nothing to
+    /// do with launching, like a mod failing to load. Defaults to 3
+    pub launch_grace_period_secs: Option<u64>,
+    /// Proxy, mirror, timeout and retry settings. Only the proxy is used
+    /// when downloading the game itself
+    pub network: NetworkConfig,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryCheck {
+    Off,
+    #[default]
+    Warn,
+    Error,
+}
+
+/// The kind of Java runtime that is downloaded
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageType {
+    Jdk,
+    #[default]
+    Jre,
+}
+
+impl ImageType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageType::Jdk => "jdk",
+            ImageType::Jre => "jre",
+        }
+    }
+}
+
+/// A mod downloaded from a direct link
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExtraMod {
+    pub url: String,
+    /// The hash the file must have, it isn't checked if unset
+    pub sha512: Option<String>,
+    /// The name of the file in the mods directory, the end of the URL is
+    /// used if unset
+    pub filename: Option<String>,
+}
+
+/// Settings that only apply to a single instance, read from the
+/// `instance.toml` file in its directory. Unset fields use the global config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstanceConfig {
+    pub max_memory: Option<u32>,
+    pub resolution: Option<Resolution>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub struct Resolution {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        paths::config_dir().join("config.toml")
+    }
+
+    /// Reads the config file, using the defaults if there is none
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if !path.try_exists()? {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        let mut table: toml::Table = toml::from_str(&content)?;
+        move_network_keys(&mut table);
+        let config: Self = toml::Value::Table(table).try_into()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Sets `game_version` in the config file, creating it if needed. The
+    /// other settings are kept but comments and formatting aren't
+    pub fn save_game_version(game_version: &str) -> Result<()> {
+        let path = Self::path();
+        let mut table: toml::Table = if path.try_exists()? {
+            toml::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            toml::Table::new()
+        };
+        table.insert("game_version".into(), game_version.into());
+
+        let content = toml::to_string(&table).map_err(|e| {
+            UklientError::ConfigError(format!("could not write config: {e}"))
+        })?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, content)?;
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(fraction) = self.max_memory_fraction {
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                return Err(UklientError::ConfigError(
+                    "max_memory_fraction must be between 0 and 1".into(),
+                ));
+            }
+        }
+
+        if let Some(template) = &self.progress_template {
+            if let Err(e) = ProgressStyle::default_bar().template(template) {
+                return Err(UklientError::ConfigError(format!(
+                    "invalid progress_template: {e}"
+                )));
+            }
+        }
+
+        self.network.validate()?;
+
+        if let Some(loader_version) = &self.loader_version {
+            if LoaderVersionRange::parse(loader_version).is_err() {
+                return Err(UklientError::ConfigError(format!(
+                    "invalid loader version {loader_version}"
+                )));
+            }
+        }
+
+        if let Some(game_version) = &self.game_version {
+            if MinecraftVersion::parse_latest_patch(game_version, &[]).is_err()
+            {
+                return Err(UklientError::ConfigError(format!(
+                    "invalid game version {game_version}"
+                )));
+            }
+        }
+
+        for (version, java) in &self.java_versions {
+            if !SUPPORTED_JAVA_VERSIONS.contains(java) {
+                return Err(UklientError::ConfigError(format!(
+                    "unsupported java version {java} for {version}"
+                )));
+            }
+        }
+
+        for version in self.java_paths.keys() {
+            if version.parse::<u8>().is_err() {
+                return Err(UklientError::ConfigError(format!(
+                    "invalid java version {version} in java_paths"
+                )));
+            }
+        }
+
+        for extra in &self.extra_mods {
+            if Url::parse(&extra.url).is_err() || extra.filename().is_none() {
+                return Err(UklientError::ConfigError(format!(
+                    "invalid extra mod {}",
+                    extra.url
+                )));
+            }
+        }
+
+        if let Some(language) = &self.language {
+            if language.is_empty()
+                || !language.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+            {
+                return Err(UklientError::ConfigError(format!(
+                    "invalid language {language}"
+                )));
+            }
+        }
+
+        validate_game_settings(self.max_memory, self.resolution)
+    }
+
+    /// The Java binary the user wants for this Java version, if any
+    pub fn java_path(&self, java_version: u8) -> Option<&PathBuf> {
+        self.java_paths.get(&java_version.to_string())
+    }
+
+    /// How often progress bars should be redrawn, if they should be redrawn
+    /// periodically at all. They never are when stderr isn't a terminal
+    pub fn progress_tick(&self) -> Option<Duration> {
+        if !Term::stderr().is_term() {
+            return None;
+        }
+
+        let tick = self.progress_tick_ms.unwrap_or(DEFAULT_PROGRESS_TICK_MS);
+        (tick > 0).then(|| Duration::from_millis(tick))
+    }
+
+    /// The style of the download progress bars
+    pub fn byte_style(&self) -> ProgressStyle {
+        self.progress_template
+            .as_deref()
+            .map_or_else(|| STYLE_BYTE.clone(), progress_style)
+    }
+
+    /// How long the game must run for to be considered started
+    pub fn launch_grace_period(&self) -> Duration {
+        Duration::from_secs(
+            self.launch_grace_period_secs
+                .unwrap_or(DEFAULT_LAUNCH_GRACE_PERIOD_SECS),
+        )
+    }
+
+    /// The Minecraft version modpacks are installed for. Unless
+    /// `exact_game_version` is set, a version without a patch is the newest
+    /// patch of that series in `supported`
+    pub fn game_version(
+        &self,
+        supported: &[MinecraftVersion],
+    ) -> Result<MinecraftVersion> {
+        let version = self.game_version.as_deref().unwrap_or(GAME_VERSION);
+        if self.exact_game_version {
+            Ok(MinecraftVersion::parse(version)?)
+        } else {
+            Ok(MinecraftVersion::parse_latest_patch(version, supported)?)
+        }
+    }
+
+    /// Whether `game_version` needs the versions the modpack supports to be
+    /// resolved
+    pub fn needs_supported_versions(&self) -> bool {
+        let version = self.game_version.as_deref().unwrap_or(GAME_VERSION);
+        !self.exact_game_version && MinecraftVersion::is_series(version)
+    }
+
+    /// Where instances are installed
+    pub fn instances_dir(&self) -> PathBuf {
+        self.instances_dir
+            .clone()
+            .unwrap_or_else(paths::instances_dir)
+    }
+
+    /// Where modpacks are extracted while being installed. A configured
+    /// directory can be shared with other programs, so only its `uklient`
+    /// subdirectory is used
+    pub fn tmp_dir(&self) -> PathBuf {
+        self.tmp_dir
+            .as_ref()
+            .map_or_else(paths::tmp_dir, |dir| dir.join("uklient"))
+    }
+
+    /// A Modrinth client, authenticated if a token is set
+    pub fn modrinth(&self) -> Result<Ferinth> {
+        Ok(Ferinth::new(
+            env!("CARGO_PKG_NAME"),
+            Some(env!("CARGO_PKG_VERSION")),
+            None,
+            self.modrinth_token.as_deref(),
+        )?)
+    }
+
+    pub fn old_generations(&self) -> usize {
+        self.old_generations.unwrap_or(DEFAULT_OLD_GENERATIONS)
+    }
+
+    /// The maximum memory for an instance, in megabytes
+    pub fn max_memory(&self, instance: &InstanceConfig) -> u32 {
+        instance.max_memory.or(self.max_memory).unwrap_or_else(|| {
+            system::recommended_max_memory().unwrap_or(DEFAULT_MAX_MEMORY)
+        })
+    }
+
+    /// How much of the machine's memory the game can safely use
+    pub fn max_memory_fraction(&self) -> f64 {
+        self.max_memory_fraction
+            .unwrap_or(DEFAULT_MAX_MEMORY_FRACTION)
+    }
+
+    /// The window size for an instance
+    pub fn resolution(&self, instance: &InstanceConfig) -> Resolution {
+        instance.resolution.or(self.resolution).unwrap_or_default()
+    }
+
+    /// Returns the Java version the user wants for this Minecraft version,
+    /// if they configured one
+    pub fn java_override(&self, game_version: &MinecraftVersion) -> Option<u8> {
+        let mut keys = vec![game_version.to_string()];
+        if let MinecraftVersion::Release { minor, .. } = game_version {
+            keys.push(format!("1.{minor}"));
+        }
+        keys.push("*".into());
+
+        keys.iter()
+            .find_map(|key| self.java_versions.get(key))
+            .copied()
+    }
+}
+
+impl ExtraMod {
+    pub fn filename(&self) -> Option<String> {
+        self.filename.clone().or_else(|| {
+            let url = Url::parse(&self.url).ok()?;
+            url.path_segments()?
+                .last()
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+        })
+    }
+}
+
+impl InstanceConfig {
+    /// Reads the config of the instance at `base_path`, if it has one
+    pub fn load(base_path: &Path) -> Result<Self> {
+        let path = base_path.join("instance.toml");
+        if !path.try_exists()? {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        let config: Self = toml::from_str(&content)?;
+        validate_game_settings(config.max_memory, config.resolution)?;
+
+        Ok(config)
+    }
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+        }
+    }
+}
+
+/// Older versions read the network settings from the top of the config, they
+/// are moved to the `[network]` section unless it sets them too
+fn move_network_keys(table: &mut toml::Table) {
+    for key in NETWORK_KEYS {
+        if let Some(value) = table.remove(*key) {
+            warn!("{key} should now be set in the [network] section");
+            let network = table
+                .entry("network")
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let Some(network) = network.as_table_mut() {
+                network.entry(*key).or_insert(value);
+            }
+        }
+    }
+}
+
+fn validate_game_settings(
+    max_memory: Option<u32>,
+    resolution: Option<Resolution>,
+) -> Result<()> {
+    if let Some(max_memory) = max_memory {
+        if max_memory < MIN_MEMORY {
+            return Err(UklientError::ConfigError(format!(
+                "max_memory must be at least {MIN_MEMORY} MB"
+            )));
+        }
+    }
+
+    if let Some(resolution) = resolution {
+        if resolution.width == 0 || resolution.height == 0 {
+            return Err(UklientError::ConfigError(
+                "resolution can't be empty".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Modrinth release channels, from the most to the least stable
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Release,
+    Beta,
+    Alpha,
+}
+
+impl Channel {
+    /// Whether a version of this type can be installed on this channel
+    pub fn allows(self, version_type: &VersionType) -> bool {
+        let channel = match version_type {
+            VersionType::Release => Channel::Release,
+            VersionType::Beta => Channel::Beta,
+            VersionType::Alpha => Channel::Alpha,
+        };
+
+        channel <= self
+    }
+}
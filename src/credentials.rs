@@ -0,0 +1,192 @@
+use crate::{paths, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use theseus::auth::Credentials;
+use tracing::info;
+use uuid::Uuid;
+
+/// Somewhere the credentials of the logged in accounts are kept
+#[async_trait]
+pub trait CredentialStore: Send + Sync {
+    /// The saved credentials of the account `id`, or of the last used
+    /// account if unset
+    async fn load(&self, id: Option<Uuid>) -> Result<Option<Credentials>>;
+    /// Saves `credentials`, replacing the ones of the same account, and
+    /// makes it the last used account
+    async fn save(&self, credentials: &Credentials) -> Result<()>;
+    /// Forgets the account `id`, returning whether it was saved
+    async fn delete(&self, id: Uuid) -> Result<bool>;
+    /// Every saved account
+    async fn list(&self) -> Result<Vec<Credentials>>;
+}
+
+/// The content of `accounts.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Accounts {
+    /// The account used when none is asked for
+    last_used: Option<Uuid>,
+    accounts: HashMap<Uuid, Credentials>,
+}
+
+/// Keeps the credentials of every account in a JSON file
+pub struct FileStore {
+    path: PathBuf,
+    /// Where older versions kept the only account
+    legacy_path: PathBuf,
+}
+
+impl Default for FileStore {
+    fn default() -> Self {
+        Self {
+            path: paths::accounts_path(),
+            legacy_path: paths::credentials_path(),
+        }
+    }
+}
+
+impl FileStore {
+    fn read(&self) -> Result<Accounts> {
+        if self.path.try_exists()? {
+            let file = File::open(&self.path)?;
+            return Ok(serde_json::from_reader(BufReader::new(file))?);
+        }
+
+        if !self.legacy_path.try_exists()? {
+            return Ok(Accounts::default());
+        }
+
+        let file = File::open(&self.legacy_path)?;
+        let credentials: Credentials =
+            serde_json::from_reader(BufReader::new(file))?;
+        let username = credentials.username.clone();
+        let accounts = Accounts {
+            last_used: Some(credentials.id),
+            accounts: HashMap::from([(credentials.id, credentials)]),
+        };
+        self.write(&accounts)?;
+        std::fs::remove_file(&self.legacy_path)?;
+        info!("Moved the saved account {username} to the list of accounts");
+        Ok(accounts)
+    }
+
+    fn write(&self, accounts: &Accounts) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = File::create(&self.path)?;
+        serde_json::to_writer(BufWriter::new(file), accounts)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CredentialStore for FileStore {
+    async fn load(&self, id: Option<Uuid>) -> Result<Option<Credentials>> {
+        let mut accounts = self.read()?;
+        Ok(id
+            .or(accounts.last_used)
+            .and_then(|id| accounts.accounts.remove(&id)))
+    }
+
+    async fn save(&self, credentials: &Credentials) -> Result<()> {
+        let mut accounts = self.read()?;
+        accounts.last_used = Some(credentials.id);
+        accounts
+            .accounts
+            .insert(credentials.id, credentials.clone());
+        self.write(&accounts)
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<bool> {
+        let mut accounts = self.read()?;
+        if accounts.accounts.remove(&id).is_none() {
+            return Ok(false);
+        }
+
+        if accounts.last_used == Some(id) {
+            accounts.last_used = accounts.accounts.keys().next().copied();
+        }
+        self.write(&accounts)?;
+        Ok(true)
+    }
+
+    async fn list(&self) -> Result<Vec<Credentials>> {
+        Ok(self.read()?.accounts.into_values().collect())
+    }
+}
+
+/// Keeps the credentials of a single account for as long as uklient runs,
+/// nothing is written to disk
+#[derive(Default)]
+pub struct MemoryStore {
+    credentials: Mutex<Option<Credentials>>,
+}
+
+#[async_trait]
+impl CredentialStore for MemoryStore {
+    async fn load(&self, id: Option<Uuid>) -> Result<Option<Credentials>> {
+        let credentials = self.credentials.lock().unwrap().clone();
+        Ok(credentials.filter(|c| id.map_or(true, |id| c.id == id)))
+    }
+
+    async fn save(&self, credentials: &Credentials) -> Result<()> {
+        *self.credentials.lock().unwrap() = Some(credentials.clone());
+        Ok(())
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<bool> {
+        let mut credentials = self.credentials.lock().unwrap();
+        if credentials.as_ref().map_or(false, |c| c.id == id) {
+            *credentials = None;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn list(&self) -> Result<Vec<Credentials>> {
+        Ok(self.load(None).await?.into_iter().collect())
+    }
+}
+
+/// Reads the accounts saved in another store, but keeps new and refreshed
+/// credentials in memory instead of writing them there
+pub struct ReadOnlyStore<S> {
+    saved: S,
+    session: MemoryStore,
+}
+
+impl<S: CredentialStore> ReadOnlyStore<S> {
+    pub fn new(saved: S) -> Self {
+        Self {
+            saved,
+            session: MemoryStore::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: CredentialStore> CredentialStore for ReadOnlyStore<S> {
+    async fn load(&self, id: Option<Uuid>) -> Result<Option<Credentials>> {
+        match self.session.load(id).await? {
+            Some(credentials) => Ok(Some(credentials)),
+            None => self.saved.load(id).await,
+        }
+    }
+
+    async fn save(&self, credentials: &Credentials) -> Result<()> {
+        self.session.save(credentials).await
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<bool> {
+        self.session.delete(id).await
+    }
+
+    async fn list(&self) -> Result<Vec<Credentials>> {
+        self.saved.list().await
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{Result, UklientError, CLIENT};
+use itertools::Itertools;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use url::Url;
+use zip::ZipArchive;
+
+const CURSEFORGE_API_URL: &str = "https://api.curseforge.com/v1";
+/// The directories the files of some kinds of projects (CurseForge calls
+/// them classes) go in. Files of other classes go in `mods`
+const CLASS_DIRECTORIES: [(u32, &str); 3] =
+    [(6, "mods"), (12, "resourcepacks"), (6552, "shaderpacks")];
+
+/// The `manifest.json` file of a CurseForge modpack
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub files: Vec<ManifestFile>,
+    /// The directory the overrides are in, inside the modpack
+    #[serde(default = "default_overrides")]
+    pub overrides: String,
+}
+
+fn default_overrides() -> String {
+    "overrides".into()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestFile {
+    #[serde(rename = "fileID")]
+    pub file_id: u32,
+    /// Files that aren't required are disabled by default, so they are
+    /// never installed
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+/// A file of the modpack, once resolved through the CurseForge API
+#[derive(Debug, Clone)]
+pub struct CurseForgeFile {
+    pub file_name: String,
+    /// The directory of the instance the file goes in
+    pub directory: &'static str,
+    pub download_url: Url,
+    pub file_length: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiFile {
+    mod_id: u32,
+    file_name: String,
+    /// Unset when the author doesn't allow third party downloads
+    download_url: Option<Url>,
+    file_length: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiMod {
+    id: u32,
+    class_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ApiResponse<T> {
+    data: T,
+}
+
+pub fn read_manifest(file: &File) -> Result<Manifest> {
+    let mut archive =
+        ZipArchive::new(file).map_err(|_| UklientError::ZipError)?;
+    let manifest = archive
+        .by_name("manifest.json")
+        .map_err(|_| UklientError::ZipError)?;
+    Ok(serde_json::from_reader(manifest)?)
+}
+
+/// Finds where the required `files` can be downloaded from. Every file has to
+/// be downloadable outside of CurseForge, or the modpack can't be installed
+pub async fn resolve_files(
+    files: &[ManifestFile],
+    api_key: Option<&str>,
+) -> Result<Vec<CurseForgeFile>> {
+    let file_ids = files
+        .iter()
+        .filter(|f| f.required)
+        .map(|f| f.file_id)
+        .collect_vec();
+    if file_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let api_key = api_key.ok_or_else(|| {
+        UklientError::CurseForgeError(
+            "curseforge_api_key must be set to install CurseForge modpacks"
+                .into(),
+        )
+    })?;
+
+    let body = serde_json::json!({ "fileIds": file_ids });
+    let files: Vec<ApiFile> = post(api_key, "mods/files", body).await?;
+
+    if files.len() != file_ids.len() {
+        return Err(UklientError::CurseForgeError(format!(
+            "only {} of the {} files were found",
+            files.len(),
+            file_ids.len()
+        )));
+    }
+
+    let mod_ids = files.iter().map(|f| f.mod_id).unique().collect_vec();
+    let directories = project_directories(&mod_ids, api_key).await?;
+
+    files
+        .into_iter()
+        .map(|file| match file.download_url {
+            Some(download_url) => Ok(CurseForgeFile {
+                directory: directories
+                    .get(&file.mod_id)
+                    .copied()
+                    .unwrap_or("mods"),
+                file_name: file.file_name,
+                download_url,
+                file_length: file.file_length,
+            }),
+            None => Err(UklientError::CurseForgeError(format!(
+                "{} can't be downloaded outside of CurseForge",
+                file.file_name
+            ))),
+        })
+        .collect()
+}
+
+/// The directory the files of each project in `mod_ids` go in, from the
+/// project's class
+async fn project_directories(
+    mod_ids: &[u32],
+    api_key: &str,
+) -> Result<HashMap<u32, &'static str>> {
+    let body = serde_json::json!({ "modIds": mod_ids });
+    let mods: Vec<ApiMod> = post(api_key, "mods", body).await?;
+
+    Ok(mods
+        .into_iter()
+        .map(|m| (m.id, class_directory(m.class_id)))
+        .collect())
+}
+
+fn class_directory(class_id: Option<u32>) -> &'static str {
+    CLASS_DIRECTORIES
+        .iter()
+        .find(|(id, _)| Some(*id) == class_id)
+        .map_or("mods", |(_, directory)| *directory)
+}
+
+/// Sends `body` to the `endpoint` of the CurseForge API, and returns the
+/// data of its response
+async fn post<T: DeserializeOwned>(
+    api_key: &str,
+    endpoint: &str,
+    body: serde_json::Value,
+) -> Result<T> {
+    let response: ApiResponse<T> = CLIENT
+        .post(format!("{CURSEFORGE_API_URL}/{endpoint}"))
+        .header("x-api-key", api_key)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| UklientError::CurseForgeError(e.to_string()))?
+        .json()
+        .await
+        .map_err(|e| UklientError::CurseForgeError(e.to_string()))?;
+
+    Ok(response.data)
+}
@@ -1,53 +1,103 @@
-use crate::{Result, UklientError, CLIENT, STYLE_BYTE};
+use crate::config::{Config, ImageType};
+use crate::paths;
+use crate::version::MinecraftVersion;
+use crate::{byte_progress_bar, Result, UklientError, CLIENT, PROGRESS};
 use flate2::bufread::GzDecoder;
-use indicatif::ProgressBar;
+use indicatif::HumanBytes;
 use itertools::Itertools;
 use libium::modpack::extract_zip;
-use libium::HOME;
 use regex::Regex;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::env::consts::{ARCH, OS};
+use std::fmt::Display;
 use std::fs::File;
+use std::io::BufReader;
 use std::ops::Deref;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
 use std::time::Duration;
-use std::{io::BufReader, path::PathBuf};
 use tar::Archive;
+use theseus::prelude::ModLoader;
 use theseus::profile::JavaSettings;
 use tokio::fs::{rename, OpenOptions};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::process::Command;
-use tracing::{error, info};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+/// How long `java -version` gets before the binary is considered broken
+const JAVA_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+const JVM_ARGS_FILE: &str = "jvm_args.txt";
+
+/// How much of the Java download is buffered before being written to disk
+const WRITE_BUFFER_SIZE: usize = 256 * 1024;
+
+/// How many downloaded JDKs of every major version are kept around
+const JDKS_KEPT_PER_MAJOR: usize = 1;
+
+/// The fingerprint of the key Adoptium signs its releases with
+const ADOPTIUM_KEY: &str = "3B04D753C9050D9A5D343F39843C48A565F8F04B";
+const KEYSERVER: &str = "keyserver.ubuntu.com";
+
+/// The Java versions that can be downloaded
+pub const SUPPORTED_JAVA_VERSIONS: &[u8] = &[8, 11, 16, 17, 18, 19, 20, 21];
+
+const JAVA_NAME: &str = if cfg!(windows) { "javaw.exe" } else { "java" };
+const ARCHIVE_EXTENSION: &str = if cfg!(windows) { "zip" } else { "tar.gz" };
+
+/// Where the JDK's files are inside the extracted directory. On macOS they
+/// are bundled like an app, e.g. `jdk-17.0.6+10/Contents/Home/bin`
+fn jdk_home(jdk_dir: &Path) -> PathBuf {
+    if cfg!(target_os = "macos") {
+        let home = jdk_dir.join("Contents").join("Home");
+        if home.is_dir() {
+            return home;
+        }
+    }
+    jdk_dir.to_path_buf()
+}
 
 pub async fn get_java_settings(
     java_version: u8,
     force_download: bool,
-) -> JavaSettings {
-    let java_name = if cfg!(windows) { "javaw.exe" } else { "java" };
-
-    // TODO fork java_locator to look for multiple java versions (cf. prism's implementation of the java locator)
-    let mut java_path =
-        if let Some(java_home_path) = find_local_java(java_version) {
-            info!("Found uklient Java: {java_home_path:?}");
-            Some(java_home_path.join("bin").join(java_name))
-        } else if let Ok(java_home) = java_locator::locate_file(java_name) {
-            info!("Found Java: {java_home:?}");
-            Some(PathBuf::from(java_home).join(java_name))
-        } else {
-            None
-        };
+    config: &Config,
+    cancel: &CancellationToken,
+) -> Result<JavaSettings> {
+    let mut java_path = if force_download {
+        None
+    } else {
+        locate_java(java_version, config).await
+    };
 
-    if force_download
-        || java_path.is_none()
-        || get_java_version(&java_path.clone().unwrap())
-            .await
-            .unwrap_or(0)
-            != java_version
-    {
-        java_path = match download_java(java_version).await {
+    if java_path.is_none() {
+        java_path = match download_java(
+            java_version,
+            config.java_image_type,
+            config,
+            cancel,
+        )
+        .await
+        {
             Ok(java_bin_path) => {
                 info!("Found downloaded Java: {java_bin_path:?}");
-                Some(java_bin_path.join(java_name))
+                let java_path = java_bin_path.join(JAVA_NAME);
+                cache_java(java_version, &java_path);
+                if let Err(e) = prune_old_java(JDKS_KEPT_PER_MAJOR, &java_path)
+                {
+                    warn!("Could not remove the older JDKs: {e}");
+                }
+                Some(java_path)
+            }
+            Err(UklientError::Cancelled) => {
+                return Err(UklientError::Cancelled)
             }
             Err(e) => {
                 error!("Error while downloading java: {e}");
@@ -60,73 +110,683 @@ pub async fn get_java_settings(
         info!("Java version: {}", get_java_version(&p).await.unwrap_or(0));
     }
 
-    JavaSettings {
+    Ok(JavaSettings {
         install: java_path,
         extra_arguments: None,
+    })
+}
+
+/// Where a Java binary was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JavaSource {
+    /// Set in the config
+    Configured,
+    /// Found by a previous launch
+    Cached,
+    /// Downloaded by uklient
+    Downloaded,
+    /// Installed on the system
+    System,
+}
+
+impl Display for JavaSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            JavaSource::Configured => "configured",
+            JavaSource::Cached => "found by a previous launch",
+            JavaSource::Downloaded => "downloaded by uklient",
+            JavaSource::System => "installed on the system",
+        })
     }
 }
 
-async fn download_java(java_version: u8) -> Result<PathBuf> {
-    let java_version = get_latest_java(java_version).await?;
-    let download_url = format!(
-        "https://api.adoptium.net/v3/binary/version/{java_version}/{OS}/{ARCH}/jdk/hotspot/normal/eclipse"
-    );
+/// Whether a Java version is already there or would have to be downloaded.
+/// It is flattened into the `--plan` output, hence the field names
+#[derive(Debug, Clone, Serialize)]
+pub struct JavaStatus {
+    #[serde(rename = "java_version")]
+    pub version: u8,
+    /// `None` if Java would have to be downloaded
+    #[serde(rename = "java_path")]
+    pub path: Option<PathBuf>,
+    #[serde(rename = "java_source")]
+    pub source: Option<JavaSource>,
+}
 
-    let tmp_dir = HOME.join(".config").join("uklient").join(".tmp");
-    let java_dir = HOME.join(".config").join("uklient");
+/// Looks for a Java binary of the given version without downloading
+/// anything, starting with the one from the config. What is found is
+/// remembered for the next launch
+async fn locate_java(java_version: u8, config: &Config) -> Option<PathBuf> {
+    let (path, source) = find_java(java_version, config, true).await?;
+    if matches!(source, JavaSource::Downloaded | JavaSource::System) {
+        cache_java(java_version, &path);
+    }
+    Some(path)
+}
 
-    let mut response = CLIENT.get(download_url).send().await?;
+/// Reports whether Java `java_version` would have to be downloaded, without
+/// downloading anything or touching the Java cache
+pub async fn java_status(java_version: u8, config: &Config) -> JavaStatus {
+    let found = find_java(java_version, config, false).await;
+    JavaStatus {
+        version: java_version,
+        source: found.as_ref().map(|(_, source)| *source),
+        path: found.map(|(path, _)| path),
+    }
+}
 
-    let extension = if cfg!(windows) { "zip" } else { "tar.gz" };
-    let out_file_path = tmp_dir
-        .join(java_version.replace('.', "-"))
-        .with_extension(extension);
+/// Looks for a Java binary of the given version. Stale Java cache entries
+/// are only forgotten if `update_cache` is set
+async fn find_java(
+    java_version: u8,
+    config: &Config,
+    update_cache: bool,
+) -> Option<(PathBuf, JavaSource)> {
+    if let Some(path) = config.java_path(java_version) {
+        match get_java_version(path).await {
+            Ok(v) if v == java_version => {
+                info!("Using configured Java: {path:?}");
+                return Some((path.clone(), JavaSource::Configured));
+            }
+            Ok(v) => {
+                warn!("{path:?} is Java {v} instead of {java_version}, ignoring it")
+            }
+            Err(e) => warn!("Could not use the configured Java {path:?}: {e}"),
+        }
+    }
+
+    if let Some(path) = cached_java(java_version, update_cache).await {
+        info!("Using Java from the last launch: {path:?}");
+        return Some((path, JavaSource::Cached));
+    }
 
+    // TODO fork java_locator to look for multiple java versions (cf. prism's implementation of the java locator)
+    let (java_path, source) =
+        if let Some(java_home_path) = find_local_java(java_version) {
+            info!("Found uklient Java: {java_home_path:?}");
+            (
+                java_home_path.join("bin").join(JAVA_NAME),
+                JavaSource::Downloaded,
+            )
+        } else if let Ok(java_home) = java_locator::locate_file(JAVA_NAME) {
+            info!("Found Java: {java_home:?}");
+            (PathBuf::from(java_home).join(JAVA_NAME), JavaSource::System)
+        } else {
+            return None;
+        };
+
+    let found = get_java_version(&java_path).await.unwrap_or(0);
+    (found == java_version).then_some((java_path, source))
+}
+
+/// The Java binary of the given version found by a previous launch, if it is
+/// still there and still that version. Stale entries are forgotten if
+/// `forget_stale` is set
+async fn cached_java(java_version: u8, forget_stale: bool) -> Option<PathBuf> {
+    let mut cache = read_java_cache();
+    let path = cache.get(&java_version)?.clone();
+    if path.is_file()
+        && get_java_version(&path).await.ok() == Some(java_version)
+    {
+        return Some(path);
+    }
+    if !forget_stale {
+        return None;
+    }
+
+    debug!("Forgetting the cached Java {java_version} at {path:?}");
+    cache.remove(&java_version);
+    if let Err(e) = write_java_cache(&cache) {
+        warn!("Could not update the Java cache: {e}");
+    }
+    None
+}
+
+/// Remembers `path` as the binary to use for `java_version`
+fn cache_java(java_version: u8, path: &Path) {
+    let mut cache = read_java_cache();
+    cache.insert(java_version, path.to_path_buf());
+    if let Err(e) = write_java_cache(&cache) {
+        warn!("Could not update the Java cache: {e}");
+    }
+}
+
+/// The Java binaries found by previous launches, by major version. A
+/// missing or unreadable cache is just empty
+fn read_java_cache() -> HashMap<u8, PathBuf> {
+    File::open(paths::java_cache_path())
+        .ok()
+        .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+        .unwrap_or_default()
+}
+
+fn write_java_cache(cache: &HashMap<u8, PathBuf>) -> Result<()> {
+    std::fs::create_dir_all(paths::config_dir())?;
+    let file = File::create(paths::java_cache_path())?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), cache)?;
+    Ok(())
+}
+
+/// Reads the JVM arguments from an instance's `jvm_args.txt`, one argument
+/// per line. Blank lines and lines starting with `#` are ignored
+pub fn read_instance_jvm_args(base_path: &Path) -> Result<Vec<String>> {
+    let path = base_path.join(JVM_ARGS_FILE);
+    if !path.try_exists()? {
+        return Ok(Vec::new());
+    }
+
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Makes sure `java_version` is recent enough for the `loader_version`
+/// build of the mod loader, bumping it to the build's minimum if it isn't
+pub fn check_loader_java(
+    java_version: u8,
+    loader: &ModLoader,
+    loader_version: Option<&str>,
+) -> u8 {
+    let floor = loader_version.map_or(8, |v| loader_java_floor(loader, v));
+    if java_version < floor {
+        warn!("{loader} {} requires Java {floor}, using it instead of Java {java_version}", loader_version.unwrap_or_default());
+        floor
+    } else {
+        java_version
+    }
+}
+
+/// The minimum Java version a loader build needs. Forge and NeoForge builds
+/// are made for a single Minecraft version, which is part of their version
+/// (`1.20.1-47.2.0`, or `20.4.80-beta` for 1.20.4), and their bootstrap
+/// refuses to start on an older Java than that version needs, whatever
+/// version the instance says it runs
+fn loader_java_floor(loader: &ModLoader, loader_version: &str) -> u8 {
+    let ModLoader::Forge = loader else {
+        return 8;
+    };
+
+    let built_for = match loader_version.split_once('-') {
+        Some((game_version, _)) if game_version.starts_with("1.") => {
+            MinecraftVersion::parse(game_version).ok()
+        }
+        // NeoForge builds start with the Minecraft minor and patch
+        _ => {
+            let mut parts = loader_version.split(|c| c == '.' || c == '-');
+            let minor = parts.next().and_then(|p| p.parse().ok());
+            let patch = parts.next().and_then(|p| p.parse().ok());
+            minor
+                .zip(patch)
+                .map(|(minor, patch)| MinecraftVersion::new(minor, patch))
+        }
+    };
+    built_for.map_or(8, |game_version| game_version.required_java_major())
+}
+
+/// Where JDKs are downloaded from, in the order they are tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JavaVendor {
+    Adoptium,
+    Zulu,
+}
+
+/// How stable the downloaded JDK builds are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ReleaseType {
+    /// Generally available builds
+    #[default]
+    Ga,
+    /// Early access builds, for Java versions that aren't released yet
+    Ea,
+}
+
+impl ReleaseType {
+    fn as_str(self) -> &'static str {
+        match self {
+            ReleaseType::Ga => "ga",
+            ReleaseType::Ea => "ea",
+        }
+    }
+}
+
+/// A JDK or JRE build that can be downloaded
+struct JavaRelease {
+    vendor: JavaVendor,
+    /// The name of the release, e.g. `jdk-17.0.6+10`
+    name: String,
+    /// The directory the build ends up in, e.g. `jdk-17.0.6+10-jre`
+    dir_name: String,
+    download_url: String,
+    /// The directory the archive extracts to, if it has to be renamed to
+    /// `dir_name` for the build to be found again
+    extracted_dir: Option<String>,
+    /// The SHA256 of the archive, when the vendor publishes it
+    checksum: Option<String>,
+    /// The size of the archive in bytes, when the vendor publishes it
+    size: Option<u64>,
+}
+
+impl JavaVendor {
+    /// Finds the latest build of `java_version` for this platform
+    async fn find_release(
+        self,
+        java_version: u8,
+        release_type: ReleaseType,
+        image_type: ImageType,
+    ) -> Result<JavaRelease> {
+        // Adoptium's JREs extract to `<release name>-jre`
+        let suffix = match image_type {
+            ImageType::Jdk => "",
+            ImageType::Jre => "-jre",
+        };
+
+        match self {
+            JavaVendor::Adoptium => {
+                let name =
+                    get_latest_java(java_version, release_type, image_type)
+                        .await?;
+                let download_url = format!(
+                    "https://api.adoptium.net/v3/binary/version/{name}/{OS}/{ARCH}/{}/hotspot/normal/eclipse",
+                    image_type.as_str()
+                );
+                let package = adoptium_package(&name, image_type).await?;
+                Ok(JavaRelease {
+                    vendor: self,
+                    dir_name: format!("{name}{suffix}"),
+                    name,
+                    download_url,
+                    extracted_dir: None,
+                    checksum: package.checksum,
+                    size: package.size,
+                })
+            }
+            JavaVendor::Zulu => {
+                let arch = if ARCH == "x86_64" { "x64" } else { ARCH };
+                let url = format!(
+                    "https://api.azul.com/metadata/v1/zulu/packages/?java_version={java_version}&os={OS}&arch={arch}&archive_type={ARCHIVE_EXTENSION}&java_package_type={}&javafx_bundled=false&release_status={}&latest=true",
+                    image_type.as_str(),
+                    release_type.as_str()
+                );
+                let packages: Vec<ZuluPackage> =
+                    CLIENT.get(url).send().await?.json().await?;
+                let package = packages
+                    .into_iter()
+                    .next()
+                    .ok_or(UklientError::JavaNotFoundError)?;
+
+                let extracted_dir = package
+                    .name
+                    .trim_end_matches(&format!(".{ARCHIVE_EXTENSION}"))
+                    .to_string();
+                // named like Adoptium's, so `find_local_java` finds it
+                let name =
+                    format!("jdk-{}", package.java_version.iter().format("."));
+                Ok(JavaRelease {
+                    vendor: self,
+                    dir_name: format!("{name}{suffix}"),
+                    name,
+                    download_url: package.download_url,
+                    extracted_dir: Some(extracted_dir),
+                    // only the details of a package have them, not the
+                    // listing
+                    checksum: None,
+                    size: None,
+                })
+            }
+        }
+    }
+}
+
+/// Finds a build of `java_version`, from the first vendor that has one. Only
+/// Adoptium is tried if signatures have to be verified. Early access builds
+/// are only used if no vendor has a GA build and the config allows them
+async fn find_java_release(
+    java_version: u8,
+    image_type: ImageType,
+    config: &Config,
+) -> Result<JavaRelease> {
+    let vendors: &[JavaVendor] = if config.verify_java_signature {
+        &[JavaVendor::Adoptium]
+    } else {
+        &[JavaVendor::Adoptium, JavaVendor::Zulu]
+    };
+    let release_types: &[ReleaseType] = if config.java_early_access {
+        &[ReleaseType::Ga, ReleaseType::Ea]
+    } else {
+        &[ReleaseType::Ga]
+    };
+
+    for &release_type in release_types {
+        for vendor in vendors {
+            match vendor
+                .find_release(java_version, release_type, image_type)
+                .await
+            {
+                Ok(release) => {
+                    if release_type == ReleaseType::Ea {
+                        warn!("Using the early access build {}, it may be unstable", release.name);
+                    }
+                    return Ok(release);
+                }
+                Err(e) => warn!(
+                    "{vendor:?} has no {} build of Java {java_version}: {e}",
+                    release_type.as_str()
+                ),
+            }
+        }
+    }
+
+    Err(UklientError::NoJavaBuild(java_version))
+}
+
+/// Downloads and extracts the latest release of the given Java version.
+/// If `cancel` is triggered, the partially downloaded file is kept around
+async fn download_java(
+    java_version: u8,
+    image_type: ImageType,
+    config: &Config,
+    cancel: &CancellationToken,
+) -> Result<PathBuf> {
+    let release = find_java_release(java_version, image_type, config).await?;
+    let java_version = release.name.clone();
+
+    let tmp_dir = paths::tmp_dir();
+    let java_dir = paths::java_dir();
+    tokio::fs::create_dir_all(&tmp_dir).await?;
+    tokio::fs::create_dir_all(&java_dir).await?;
+
+    let out_file_path = tmp_dir
+        .join(release.dir_name.replace('.', "-"))
+        .with_extension(ARCHIVE_EXTENSION);
     let temp_file_path = out_file_path.with_extension("part");
-    let mut temp_file = OpenOptions::new()
-        .read(true)
+
+    let downloaded = tokio::fs::metadata(&temp_file_path)
+        .await
+        .map_or(0, |m| m.len());
+    let (mut response, offset) =
+        request_java_archive(&release.download_url, downloaded, release.size)
+            .await?;
+    if offset > 0 {
+        info!("Resuming the Java download from {}", HumanBytes(offset));
+    }
+
+    let temp_file = OpenOptions::new()
         .write(true)
-        .append(true)
         .create(true)
+        .append(offset > 0)
+        .truncate(offset == 0)
         .open(&temp_file_path)
         .await?;
+    let mut temp_file = BufWriter::with_capacity(WRITE_BUFFER_SIZE, temp_file);
 
-    info!("Downloading Java {java_version}");
-    let progress_bar = ProgressBar::new(response.content_length().unwrap_or(0))
-        .with_style(STYLE_BYTE.clone());
-    progress_bar.enable_steady_tick(Duration::from_millis(100));
+    info!("Downloading Java {java_version} from {:?}", release.vendor);
+    let progress_bar = PROGRESS.add(byte_progress_bar(
+        offset + response.content_length().unwrap_or(0),
+        config,
+    ));
+    progress_bar.set_position(offset);
+
+    loop {
+        let chunk = tokio::select! {
+            _ = cancel.cancelled() => {
+                temp_file.flush().await?;
+                progress_bar.abandon();
+                info!("Cancelled the Java download");
+                return Err(UklientError::Cancelled);
+            }
+            chunk = response.chunk() => chunk?,
+        };
 
-    while let Some(chunk) = response.chunk().await? {
+        let Some(chunk) = chunk else { break };
         temp_file.write_all(&chunk).await?;
         progress_bar.inc(chunk.len() as u64);
     }
+    temp_file.flush().await?;
     rename(&temp_file_path, &out_file_path).await?;
 
     progress_bar.finish();
     info!("Finished downloading Java!");
 
-    let file = File::open(&out_file_path)?;
+    match &release.checksum {
+        Some(expected) => {
+            if sha256_file(&out_file_path)? != expected.to_ascii_lowercase() {
+                tokio::fs::remove_file(&out_file_path).await?;
+                return Err(UklientError::ChecksumMismatch {
+                    file: java_version,
+                });
+            }
+        }
+        None => debug!("No checksum for Java {java_version}, not checking it"),
+    }
+
+    if config.verify_java_signature {
+        if let Err(e) =
+            verify_signature(&out_file_path, &java_version, image_type).await
+        {
+            tokio::fs::remove_file(&out_file_path).await?;
+            return Err(e);
+        }
+        info!("Verified the signature of Java {java_version}");
+    }
+
+    let extracted = java_dir.join(
+        release
+            .extracted_dir
+            .as_deref()
+            .unwrap_or(&release.dir_name),
+    );
+    if let Err(e) = extract_java(&out_file_path, &java_dir).await {
+        debug!("Could not extract {out_file_path:?}: {e}");
+        // the archive may be what's broken, it's downloaded again next time
+        tokio::fs::remove_file(&out_file_path).await?;
+        if extracted.exists() {
+            std::fs::remove_dir_all(&extracted)?;
+        }
+        return Err(UklientError::ExtractError {
+            phase: "java",
+            path: extracted,
+        });
+    }
+
+    let target = java_dir.join(&release.dir_name);
+    if let Some(extracted_dir) = &release.extracted_dir {
+        if target.exists() {
+            std::fs::remove_dir_all(&target)?;
+        }
+        std::fs::rename(java_dir.join(extracted_dir), &target)?;
+    }
+    let bin = jdk_home(&target).join("bin");
+    if bin.is_dir() {
+        return Ok(bin);
+    }
+
+    java_dir
+        .read_dir()?
+        .filter_map(|res| res.ok())
+        .map(|dir| jdk_home(&dir.path()).join("bin"))
+        .find(|p| p.is_dir())
+        .ok_or(UklientError::JavaNotFoundError)
+}
+
+async fn extract_java(archive: &Path, java_dir: &Path) -> Result<()> {
+    let file = File::open(archive)?;
     if cfg!(windows) {
-        extract_zip(file, &java_dir)
+        extract_zip(file, java_dir)
             .await
             .map_err(|_| UklientError::ZipError)?;
     } else {
         let reader = BufReader::new(file);
         let tar = GzDecoder::new(reader);
         let mut archive = Archive::new(tar);
-        archive.unpack(&java_dir)?;
+        archive.unpack(java_dir)?;
     }
+    Ok(())
+}
 
-    java_dir
-        .read_dir()?
-        .filter_map(|res| res.map(|dir| dir.path().join("bin")).ok())
-        .find(|p| p.is_dir())
+/// Requests the archive at `url`, only asking for what comes after the
+/// `downloaded` bytes already on disk. Returns the response along with where
+/// it starts, which is 0 when the server can't resume the download and the
+/// file has to be downloaded again from scratch. That is also the case when
+/// the whole file isn't `expected_size` bytes long, since the bytes on disk
+/// are then from another archive
+async fn request_java_archive(
+    url: &str,
+    downloaded: u64,
+    expected_size: Option<u64>,
+) -> Result<(Response, u64)> {
+    if downloaded > 0 {
+        let response = CLIENT
+            .get(url)
+            .header(RANGE, format!("bytes={downloaded}-"))
+            .send()
+            .await?;
+
+        match (response.status(), content_range(&response)) {
+            (StatusCode::PARTIAL_CONTENT, Some((start, total)))
+                if start == downloaded =>
+            {
+                match total.zip(expected_size) {
+                    // the partial file is truncated by the caller
+                    Some((total, expected)) if total != expected => debug!(
+                        "The Java archive is {total} bytes instead of {expected}, starting over"
+                    ),
+                    _ => return Ok((response, downloaded)),
+                }
+            }
+            // the range was ignored, this is the whole file
+            (StatusCode::OK, _) => return Ok((response, 0)),
+            (status, _) => {
+                debug!("Could not resume the Java download ({status})")
+            }
+        }
+    }
+
+    let response = CLIENT.get(url).send().await?.error_for_status()?;
+    Ok((response, 0))
+}
+
+/// Where the content of a partial response starts in the whole file, and
+/// how long that file is if the server says so
+fn content_range(response: &Response) -> Option<(u64, Option<u64>)> {
+    parse_content_range(response.headers().get(CONTENT_RANGE)?.to_str().ok()?)
+}
+
+/// Reads a `bytes <start>-<end>/<total>` content range, where the total can
+/// be `*` when it isn't known
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split('-').next()?.parse().ok()?;
+    Some((start, total.parse().ok()))
+}
+
+/// Checks `archive` against the signature Adoptium published for it, using
+/// a keyring only uklient uses so that no other key is trusted
+async fn verify_signature(
+    archive: &Path,
+    release_name: &str,
+    image_type: ImageType,
+) -> Result<()> {
+    let signature_link = adoptium_package(release_name, image_type)
+        .await?
+        .signature_link
+        .ok_or_else(|| {
+            UklientError::SignatureError("no signature was published".into())
+        })?;
+
+    let signature = CLIENT.get(signature_link).send().await?.bytes().await?;
+    let signature_path = archive.with_extension("sig");
+    tokio::fs::write(&signature_path, signature).await?;
+
+    let gnupg_home = paths::gnupg_dir();
+    tokio::fs::create_dir_all(&gnupg_home).await?;
+
+    let output = gpg(&gnupg_home)
+        .args(["--list-keys", ADOPTIUM_KEY])
+        .output()
+        .await?;
+    if !output.status.success() {
+        info!("Fetching the Adoptium signing key");
+        let output = gpg(&gnupg_home)
+            .args(["--keyserver", KEYSERVER, "--recv-keys", ADOPTIUM_KEY])
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(UklientError::SignatureError(
+                "could not fetch the Adoptium key".into(),
+            ));
+        }
+    }
+
+    let output = gpg(&gnupg_home)
+        .args(["--status-fd", "1", "--verify"])
+        .arg(&signature_path)
+        .arg(archive)
+        .output()
+        .await?;
+    tokio::fs::remove_file(&signature_path).await?;
+
+    // VALIDSIG lines end with the fingerprint of the primary key, even when
+    // a subkey made the signature
+    let valid = String::from_utf8_lossy(&output.stdout).lines().any(|l| {
+        l.starts_with("[GNUPG:] VALIDSIG") && l.ends_with(ADOPTIUM_KEY)
+    });
+    if output.status.success() && valid {
+        Ok(())
+    } else {
+        Err(UklientError::SignatureError(format!(
+            "{} is not signed by Adoptium",
+            archive.display()
+        )))
+    }
+}
+
+/// The archive Adoptium publishes for `release_name` on this platform
+async fn adoptium_package(
+    release_name: &str,
+    image_type: ImageType,
+) -> Result<Package> {
+    let url = format!(
+        "https://api.adoptium.net/v3/assets/release_name/eclipse/{release_name}?architecture={ARCH}&image_type={}&jvm_impl=hotspot&os={OS}&project=jdk",
+        image_type.as_str()
+    );
+    let release: Release = CLIENT.get(url).send().await?.json().await?;
+    release
+        .binaries
+        .into_iter()
+        .next()
+        .map(|b| b.package)
         .ok_or(UklientError::JavaNotFoundError)
 }
 
-async fn get_latest_java(java_version: u8) -> Result<String> {
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn gpg(gnupg_home: &Path) -> Command {
+    let mut command = Command::new("gpg");
+    command
+        .arg("--batch")
+        .arg("--homedir")
+        .arg(gnupg_home)
+        .kill_on_drop(true);
+    command
+}
+
+async fn get_latest_java(
+    java_version: u8,
+    release_type: ReleaseType,
+    image_type: ImageType,
+) -> Result<String> {
     let url = format!(
-        "https://api.adoptium.net/v3/info/release_names?project=jdk&release_type=ga&version=[{java_version},{})",
+        "https://api.adoptium.net/v3/info/release_names?project=jdk&image_type={}&release_type={}&version=[{java_version},{})",
+        image_type.as_str(),
+        release_type.as_str(),
         java_version+1
     );
 
@@ -140,55 +800,150 @@ async fn get_latest_java(java_version: u8) -> Result<String> {
         .ok_or(UklientError::JavaNotFoundError)
 }
 
+/// Every Java installation uklient can find, as its major version and the
+/// path to its binary. The system's Java comes after the downloaded ones
+pub async fn list_installed_javas() -> Vec<(u8, PathBuf)> {
+    let mut binaries = local_jdks()
+        .into_iter()
+        .map(|p| jdk_home(&p).join("bin").join(JAVA_NAME))
+        .collect::<Vec<_>>();
+    if let Ok(java_home) = java_locator::locate_file(JAVA_NAME) {
+        binaries.push(PathBuf::from(java_home).join(JAVA_NAME));
+    }
+
+    let mut javas = Vec::new();
+    for binary in binaries {
+        match get_java_version(&binary).await {
+            Ok(version) => javas.push((version, binary)),
+            Err(e) => debug!("Skipping {binary:?}: {e}"),
+        }
+    }
+    javas
+}
+
 fn find_local_java(java_version: u8) -> Option<PathBuf> {
-    let uklient_dir = HOME.join(".config").join("uklient");
-    let pattern =
-        Regex::new(format!(r"jdk-{java_version}(?:\.\d+)+(?:\+\d+)?").as_str())
-            .unwrap();
-
-    if let Ok(dir) = uklient_dir.read_dir() {
-        let java_name = dir
-            .filter_map(|res| res.ok())
-            .filter_map(|e| e.path().file_name().map(|s| s.to_os_string()))
-            .filter(|n| pattern.find(&n.to_string_lossy()).is_some())
-            .sorted()
-            .rev()
-            .next();
-
-        java_name.map(|name| uklient_dir.join(name))
-    } else {
-        None
+    let pattern = Regex::new(
+        format!(r"^jdk-{java_version}(?:\.\d+)*(?:\+\d+)?(?:-jre)?$").as_str(),
+    )
+    .unwrap();
+
+    local_jdks()
+        .into_iter()
+        .find(|p| {
+            p.file_name()
+                .map_or(false, |n| pattern.is_match(&n.to_string_lossy()))
+        })
+        .map(|p| jdk_home(&p))
+}
+
+/// Deletes the JDKs uklient downloaded, except for the newest
+/// `keep_per_major` of every major version and the one `in_use` is part of
+fn prune_old_java(keep_per_major: usize, in_use: &Path) -> Result<()> {
+    let mut kept = HashMap::new();
+    for jdk in local_jdks() {
+        let Some(&major) = jdk_version_key(&jdk).first() else {
+            continue;
+        };
+        let count = kept.entry(major).or_insert(0);
+        if *count < keep_per_major || in_use.starts_with(&jdk) {
+            *count += 1;
+            continue;
+        }
+
+        info!("Removing the old JDK {jdk:?}");
+        std::fs::remove_dir_all(&jdk)?;
     }
+    Ok(())
 }
 
-async fn get_java_version(exec_path: &Path) -> Result<u8> {
-    let regex = Regex::new(r#"version "(\d+\.\d+\.\d+)(?:_\d+)?""#).unwrap();
+/// The JDKs downloaded by uklient, from the newest to the oldest
+fn local_jdks() -> Vec<PathBuf> {
+    let Ok(dir) = paths::java_dir().read_dir() else {
+        return Vec::new();
+    };
 
+    dir.filter_map(|res| res.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.is_dir()
+                && p.file_name()
+                    .map_or(false, |n| n.to_string_lossy().starts_with("jdk"))
+        })
+        .sorted_by_key(|p| Reverse(jdk_version_key(p)))
+        .collect()
+}
+
+/// Sort key for JDK directories (`jdk-17.0.10+7`), so that their versions
+/// are compared as numbers rather than as text
+fn jdk_version_key(path: &Path) -> Vec<u32> {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|n| n.parse().ok())
+        .collect()
+}
+
+async fn get_java_version(exec_path: &Path) -> Result<u8> {
     let mut command = Command::new(exec_path.as_os_str());
-    command.arg("-version");
+    // a wrapper script waiting for input would otherwise never exit
+    command
+        .arg("-version")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
 
-    let output = command.output().await?;
+    let output = match timeout(JAVA_VERSION_TIMEOUT, command.output()).await {
+        Ok(output) => output?,
+        Err(_) => {
+            warn!("{exec_path:?} took too long to report its version");
+            return Err(UklientError::JavaVersionTimeout);
+        }
+    };
     let mut text = String::from_utf8_lossy(&output.stdout).to_string();
     text.push_str(String::from_utf8_lossy(&output.stderr).deref());
 
-    if let Some(version) = regex.captures(text.as_str()).and_then(|c| c.get(1))
-    {
-        let mut parts = version.as_str().split('.');
-        let major = parts
-            .next()
-            .and_then(|s| s.parse::<u8>().ok())
-            .ok_or(UklientError::MetaError("java major"))?;
-
-        match major {
-            0 => Err(UklientError::MetaError("java major")),
-            1 => match parts.next().and_then(|s| s.parse::<u8>().ok()) {
-                Some(n) => Ok(n),
-                None => Err(UklientError::MetaError("java minor")),
-            },
-            v => Ok(v),
-        }
+    let version = parse_java_version(&text)?;
+    if !is_64_bit(&text) {
+        warn!("{exec_path:?} is a 32-bit Java, which can't run Minecraft");
+        return Err(UklientError::Java32BitError);
+    }
+
+    Ok(version)
+}
+
+/// Extracts the major version from the output of `java -version`. The first
+/// release of a version only reports its major (`"21"`), and Java 8 reports
+/// itself as `"1.8.0_352"`
+fn parse_java_version(text: &str) -> Result<u8> {
+    let regex = Regex::new(r#"version "(\d+)(?:\.(\d+))?[^"]*""#).unwrap();
+    let captures = regex
+        .captures(text)
+        .ok_or(UklientError::MetaError("java"))?;
+
+    let major = captures[1]
+        .parse::<u8>()
+        .map_err(|_| UklientError::MetaError("java major"))?;
+    match major {
+        0 => Err(UklientError::MetaError("java major")),
+        1 => captures
+            .get(2)
+            .and_then(|m| m.as_str().parse::<u8>().ok())
+            .ok_or(UklientError::MetaError("java minor")),
+        v => Ok(v),
+    }
+}
+
+/// Whether the output of `java -version` comes from a 64-bit JVM. Some JVMs
+/// don't mention their bitness, they are assumed to match uklient's, since
+/// a 64-bit uklient is almost always paired with a 64-bit Java
+fn is_64_bit(text: &str) -> bool {
+    let text = text.to_ascii_lowercase();
+    if text.contains("64-bit") {
+        true
+    } else if text.contains("32-bit") {
+        false
     } else {
-        Err(UklientError::MetaError("java"))
+        cfg!(target_pointer_width = "64")
     }
 }
 
@@ -196,3 +951,103 @@ async fn get_java_version(exec_path: &Path) -> Result<u8> {
 struct ReleaseNames {
     releases: Vec<String>,
 }
+
+/// A JDK package from Azul's metadata API
+#[derive(Debug, Clone, Deserialize)]
+struct ZuluPackage {
+    /// The name of the archive
+    name: String,
+    download_url: String,
+    java_version: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Release {
+    binaries: Vec<Binary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Binary {
+    package: Package,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Package {
+    signature_link: Option<String>,
+    checksum: Option<String>,
+    size: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMURIN_17: &str = r#"openjdk version "17.0.10" 2024-01-16
+OpenJDK Runtime Environment Temurin-17.0.10+7 (build 17.0.10+7)
+OpenJDK 64-Bit Server VM Temurin-17.0.10+7 (build 17.0.10+7, mixed mode, sharing)"#;
+    const ORACLE_8_CLIENT: &str = r#"java version "1.8.0_401"
+Java(TM) SE Runtime Environment (build 1.8.0_401-b10)
+Java HotSpot(TM) Client VM (build 25.401-b10, mixed mode, sharing)"#;
+    const IBM_8_32_BIT: &str = r#"java version "1.8.0_381"
+Java(TM) SE Runtime Environment (build 8.0.8.10 - pxi3280sr8fp10-20230721_01(SR8 FP10))
+IBM J9 VM (build 2.9, JRE 1.8.0 Linux x86-32-Bit 20230711_55321 (JIT enabled, AOT enabled)"#;
+    const OPENJ9_21: &str = r#"openjdk version "21.0.2" 2024-01-16 LTS
+IBM Semeru Runtime Open Edition 21.0.2.0 (build 21.0.2+13-LTS)
+Eclipse OpenJ9 VM 21.0.2.0 (build openj9-0.43.0, JRE 21 Linux amd64-64-Bit Compressed References 20240116_95 (JIT enabled, AOT enabled)"#;
+
+    #[test]
+    fn parses_java_versions() {
+        assert_eq!(parse_java_version(TEMURIN_17).unwrap(), 17);
+        assert_eq!(parse_java_version(ORACLE_8_CLIENT).unwrap(), 8);
+        assert_eq!(parse_java_version(OPENJ9_21).unwrap(), 21);
+    }
+
+    #[test]
+    fn parses_content_ranges() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/200"),
+            Some((100, Some(200)))
+        );
+        assert_eq!(parse_content_range("bytes 100-199/*"), Some((100, None)));
+        assert_eq!(parse_content_range("bytes */200"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+    }
+
+    #[test]
+    fn detects_64_bit_jvms() {
+        assert!(is_64_bit(TEMURIN_17));
+        assert!(is_64_bit(OPENJ9_21));
+        assert!(!is_64_bit(IBM_8_32_BIT));
+    }
+
+    #[test]
+    fn forge_builds_need_the_java_of_their_minecraft_version() {
+        let forge = |java, version| {
+            check_loader_java(java, &ModLoader::Forge, Some(version))
+        };
+        assert_eq!(forge(8, "1.18.2-40.2.0"), 17);
+        assert_eq!(forge(17, "1.20.6-50.1.0"), 21);
+        assert_eq!(forge(21, "1.18.2-40.2.0"), 21);
+        assert_eq!(forge(8, "1.16.5-36.2.39"), 8);
+        assert_eq!(forge(8, "20.4.80-beta"), 17);
+        assert_eq!(forge(17, "21.0.167"), 21);
+        assert_eq!(check_loader_java(8, &ModLoader::Forge, None), 8);
+    }
+
+    #[test]
+    fn other_loaders_keep_the_java_version() {
+        for loader in [ModLoader::Vanilla, ModLoader::Fabric, ModLoader::Quilt]
+        {
+            assert_eq!(check_loader_java(8, &loader, Some("0.15.11")), 8);
+            assert_eq!(check_loader_java(17, &loader, Some("0.15.11")), 17);
+        }
+    }
+
+    #[test]
+    fn unknown_bitness_matches_uklient() {
+        assert_eq!(
+            is_64_bit(ORACLE_8_CLIENT),
+            cfg!(target_pointer_width = "64")
+        );
+    }
+}
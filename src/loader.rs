@@ -0,0 +1,65 @@
+use std::fs::{read_dir, File};
+use std::path::Path;
+use theseus::prelude::ModLoader;
+use theseus::profile;
+use tracing::debug;
+use zip::ZipArchive;
+
+/// Works out which loader the instance in `base_path` uses, from the mods
+/// installed in it and from its profile. Returns `None` if it can't tell,
+/// e.g. when there are no mods and no profile, or mods for several loaders
+pub async fn detect_loader(base_path: &Path) -> Option<ModLoader> {
+    let profile_loader = match profile::get(base_path).await {
+        Ok(Some(profile)) => Some(profile.metadata.loader),
+        _ => None,
+    };
+
+    let mut found = Vec::new();
+    if let Ok(entries) = read_dir(base_path.join("mods")) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map_or(true, |e| e != "jar") {
+                continue;
+            }
+            if let Some(loader) = jar_loader(&path) {
+                if !found.contains(&loader) {
+                    found.push(loader);
+                }
+            }
+        }
+    }
+
+    // quilt also loads fabric mods, so packs for it often contain both
+    if found.contains(&ModLoader::Quilt) {
+        found.retain(|&l| l != ModLoader::Fabric);
+    }
+
+    match found.as_slice() {
+        [] => profile_loader,
+        [loader] => Some(*loader),
+        _ => {
+            debug!("Found mods for several loaders: {found:?}");
+            profile_loader.filter(|l| found.contains(l))
+        }
+    }
+}
+
+/// The loader the mod at `path` is made for, from the metadata file it ships.
+/// NeoForge mods count as Forge ones, since theseus launches both the same way
+fn jar_loader(path: &Path) -> Option<ModLoader> {
+    let archive = ZipArchive::new(File::open(path).ok()?).ok()?;
+    let has = |name: &str| archive.file_names().any(|f| f == name);
+
+    if has("quilt.mod.json") {
+        Some(ModLoader::Quilt)
+    } else if has("fabric.mod.json") {
+        Some(ModLoader::Fabric)
+    } else if has("META-INF/mods.toml")
+        || has("META-INF/neoforge.mods.toml")
+        || has("mcmod.info")
+    {
+        Some(ModLoader::Forge)
+    } else {
+        None
+    }
+}
@@ -1,50 +1,115 @@
 mod auth;
+mod config;
+mod credentials;
+mod curseforge;
 mod java;
+mod loader;
+mod migrate;
 mod modpack;
+mod network;
+mod options;
+mod paths;
+mod shutdown;
+mod system;
 mod version;
 
-use crate::auth::{get_credentials, refresh_credentials};
-use crate::java::get_java_settings;
-use crate::modpack::get_metadata;
-use crate::version::MinecraftVersion;
+use crate::auth::{
+    get_credentials, needs_refresh, refresh_credentials, DEFAULT_SCOPES,
+};
+use crate::config::{Channel, Config, InstanceConfig};
+use crate::credentials::{CredentialStore, FileStore, ReadOnlyStore};
+use crate::java::{
+    check_loader_java, get_java_settings, java_status, list_installed_javas,
+    read_instance_jvm_args, JavaStatus,
+};
+use crate::modpack::{
+    get_metadata, supported_game_versions, InstallOptions, InstallPlan,
+    InstallSummary, Side,
+};
+use crate::version::{LoaderVersionRange, MinecraftVersion};
 use crate::UklientError::MetaError;
 use auth::get_device_code;
-use clap::Parser;
+use chrono::Local;
+use clap::{Parser, Subcommand, ValueEnum};
 use daedalus::modded::LoaderVersion;
-use indicatif::ProgressStyle;
+use dialoguer::Confirm;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use itertools::Itertools;
 use reqwest::Client;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::OsString;
 use tracing::{debug, info, warn};
 
-use libium::HOME;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use once_cell::sync::Lazy;
 use theseus::auth::Credentials;
 use theseus::data::{MemorySettings, WindowSize};
+use theseus::prelude::ModLoader;
 use theseus::profile;
 use theseus::profile::Profile;
 use thiserror::Error;
+use tokio::io::{self, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::process::Child;
 use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
 type Result<T> = std::result::Result<T, UklientError>;
 
 const FABRIC_META_URL: &str = "https://meta.fabricmc.net/v2";
 const QUILT_META_URL: &str = "https://meta.quiltmc.org/v3";
-const ONE_SEVENTEEN: MinecraftVersion = MinecraftVersion {
-    minor: 17,
-    patch: 0,
-};
+const FORGE_PROMOTIONS_URL: &str =
+    "https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json";
+/// Forge doesn't publish launcher profiles, Modrinth builds them
+const FORGE_META_URL: &str = "https://meta.modrinth.com/forge/v0";
+const NEOFORGE_MAVEN_METADATA_URL: &str =
+    "https://maven.neoforged.net/releases/net/neoforged/neoforge/maven-metadata.xml";
+/// Neither does NeoForge
+const NEOFORGE_META_URL: &str = "https://meta.modrinth.com/neo/v0";
+const GAME_VERSION: &str = "1.19.3";
+/// How many lines of the game's stderr are shown when it fails to start
+const GAME_OUTPUT_TAIL: usize = 50;
+/// Clearing more than this many bytes of old files needs a confirmation
+const CLEAR_OLD_CONFIRM_SIZE: u64 = 256 * 1024 * 1024;
+/// How long loader metadata is used before it is downloaded again
+const META_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
 pub static STYLE_BYTE: Lazy<ProgressStyle> = Lazy::new(|| {
-    ProgressStyle::default_bar()
-        .template("{bytes_per_sec} [{bar:30}] {bytes}/{total_bytes}")
-        .expect("Progess bar template parse failure")
-        .progress_chars("#>-")
+    progress_style("{bytes_per_sec} [{bar:30}] {bytes}/{total_bytes}")
 });
-pub static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+pub static STYLE_FILES: Lazy<ProgressStyle> =
+    Lazy::new(|| progress_style("[{bar:30}] {pos}/{len} files"));
+/// Built with the settings given to `network::init` the first time it is used
+pub static CLIENT: Lazy<Client> =
+    Lazy::new(|| network::build_client(&network::settings()));
+/// Every progress bar is drawn through this, so that bars shown at the same
+/// time don't draw over each other
+pub static PROGRESS: Lazy<MultiProgress> = Lazy::new(MultiProgress::new);
+
+/// The style of a bar drawn with `template`, or indicatif's default one if
+/// the template can't be parsed
+pub fn progress_style(template: &str) -> ProgressStyle {
+    match ProgressStyle::default_bar().template(template) {
+        Ok(style) => style.progress_chars("#>-"),
+        Err(e) => {
+            warn!("Invalid progress bar template {template:?}, using the default one: {e}");
+            ProgressStyle::default_bar()
+        }
+    }
+}
+
+/// Creates a progress bar for `len` bytes, redrawn as often as configured
+pub fn byte_progress_bar(len: u64, config: &Config) -> ProgressBar {
+    let progress_bar = ProgressBar::new(len).with_style(config.byte_style());
+    if let Some(tick) = config.progress_tick() {
+        progress_bar.enable_steady_tick(tick);
+    }
+    progress_bar
+}
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -55,82 +120,1418 @@ struct Args {
     force_java_download: bool,
     #[arg(long, help = "don't launch the game, only install the modpack")]
     no_launch: bool,
+    #[arg(long, help = "list the minecraft versions the modpack supports")]
+    list_versions: bool,
+    #[arg(long, help = "reset the profile if it can't be read")]
+    reset_profile: bool,
+    #[arg(long, help = "don't install the modpack's resource packs")]
+    no_resourcepacks: bool,
+    #[arg(long, help = "the least stable modpack versions to install")]
+    channel: Option<Channel>,
+    #[arg(
+        long,
+        help = "the loader version (0.14.21) or range (\">=0.14, <0.15\") to use instead of the latest one"
+    )]
+    loader_version: Option<String>,
+    #[arg(long, help = "how many modpack files to download at once")]
+    concurrency: Option<usize>,
+    #[arg(long, help = "don't delete older files from .old after updating")]
+    keep_old: bool,
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..), help = "the width of the game window, for this launch only")]
+    width: Option<u16>,
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..), help = "the height of the game window, for this launch only")]
+    height: Option<u16>,
+    #[arg(long, help = "launch the game in fullscreen, for this launch only")]
+    fullscreen: bool,
+    #[arg(long, help = "show the game's output in the terminal")]
+    game_output: bool,
+    #[arg(
+        long,
+        help = "use the saved accounts, but never write new or refreshed credentials to disk"
+    )]
+    no_save_credentials: bool,
+    #[arg(long, help = "never prompt, use the defaults instead")]
+    non_interactive: bool,
+    #[arg(long, help = "install the modpack's optional files without asking")]
+    include_optional: bool,
+    #[arg(
+        long = "optional",
+        value_name = "PATH",
+        help = "install this optional file without asking, can be repeated"
+    )]
+    optional_files: Vec<String>,
+    #[arg(long, help = "leave out the mods listed in low_end_excluded")]
+    low_end: bool,
+    #[arg(
+        long,
+        help = "install the modpack's server files instead, without launching"
+    )]
+    server: bool,
+    #[arg(
+        long,
+        help = "check that the modpack is installed correctly, then exit"
+    )]
+    verify_only: bool,
+    #[arg(long, help = "show what would be installed and launched, then exit")]
+    plan: bool,
+    #[arg(long, value_enum, default_value_t = Format::Text, help = "the format of the plan and verification report")]
+    format: Format,
+    #[arg(
+        long,
+        help = "the saved account to use, by username or UUID, instead of the last used one"
+    )]
+    account: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    #[command(
+        about = "log into a minecraft account and save its credentials, next to the other accounts"
+    )]
+    Login,
+    #[command(about = "remove the saved credentials of an account")]
+    Logout,
+    #[command(about = "list the saved accounts")]
+    Accounts,
+    #[command(about = "show the account that is currently logged in")]
+    Whoami,
+    #[command(about = "re-download the missing or corrupt modpack files")]
+    Repair,
+    #[command(
+        about = "show the modpack version and loader that would be used"
+    )]
+    Info,
+    #[command(about = "undo the last update of the modpack's files")]
+    Rollback,
+    #[command(about = "delete the files that were moved to .old")]
+    ClearOld,
+    #[command(
+        about = "move the installed modpack to another minecraft version"
+    )]
+    Upgrade {
+        #[arg(help = "the minecraft version to move to")]
+        version: String,
+    },
+    #[command(about = "install a modpack file from the disk in a directory")]
+    InstallFile {
+        #[arg(help = "the .mrpack file to install")]
+        file: PathBuf,
+        #[arg(help = "the directory to install it in")]
+        directory: PathBuf,
+    },
+    #[command(about = "print the json schema of the config file")]
+    ConfigSchema {
+        #[arg(long, help = "write the schema to this file instead")]
+        output: Option<PathBuf>,
+    },
+    #[command(
+        about = "install everything and log in, to launch without internet later"
+    )]
+    Prepare,
+    #[command(about = "install every modpack listed in a file")]
+    Batch {
+        #[arg(
+            help = "a file with a modpack id and a minecraft version per line"
+        )]
+        file: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// Everything a launch would use and do
+#[derive(Debug, Serialize)]
+struct LaunchPlan {
+    game_version: String,
+    #[serde(flatten)]
+    java: JavaStatus,
+    loader: String,
+    loader_version: Option<String>,
+    /// The URL of the loader's profile JSON
+    loader_profile: Option<String>,
+    instance: PathBuf,
+    modpack: InstallPlan,
+}
+
+/// Whether the user can be prompted: it wasn't turned off, and someone is
+/// there to answer, which isn't the case in CI or when input is piped
+fn is_interactive(args: &Args) -> bool {
+    !args.non_interactive && console::user_attended()
+}
+
+/// Applies the command line arguments, then the environment variables, over
+/// the config file. See the README for every supported variable
+fn apply_overrides(args: &mut Args, config: &mut Config) -> Result<()> {
+    if let Some(channel) = args.channel {
+        config.release_channel = channel;
+    }
+    if let Some(concurrency) = args.concurrency {
+        config.network.download_concurrency = Some(concurrency);
+    }
+    config.keep_old |= args.keep_old;
+    if let Some(loader_version) = &args.loader_version {
+        config.loader_version = Some(loader_version.clone());
+    }
+
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    if let Some(modpack_id) = var("UKLIENT_MODPACK_ID") {
+        args.modpack_id = modpack_id;
+    }
+    if let Some(game_version) = var("UKLIENT_GAME_VERSION") {
+        config.game_version = Some(game_version);
+    }
+    if let Some(max_memory) = var("UKLIENT_MAX_MEMORY") {
+        let max_memory = max_memory.parse().map_err(|_| {
+            UklientError::ConfigError(format!(
+                "invalid UKLIENT_MAX_MEMORY {max_memory}"
+            ))
+        })?;
+        config.max_memory = Some(max_memory);
+    }
+    if let Some(instances_dir) = var("UKLIENT_INSTANCES_DIR") {
+        config.instances_dir = Some(instances_dir.into());
+    }
+    if let Some(token) = var("UKLIENT_MODRINTH_TOKEN") {
+        config.modrinth_token = Some(token);
+    }
+
+    config.validate()
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let format = tracing_subscriber::fmt::format().with_target(false);
     tracing_subscriber::fmt().event_format(format).init();
-    let args = Args::parse();
+    let mut args = Args::parse();
+    if let Err(e) = migrate::migrate().await {
+        warn!("Could not move files from where uklient used to keep them: {e}");
+    }
+    let mut config = Config::load()?;
+    apply_overrides(&mut args, &mut config)?;
+    network::init(&config.network);
 
-    let game_version = MinecraftVersion::parse("1.19.3")?;
-    let java_version: u8 = if game_version >= ONE_SEVENTEEN { 17 } else { 8 };
-    let java = get_java_settings(java_version, args.force_java_download).await;
+    match args.command {
+        Some(Command::Login) => login().await,
+        Some(Command::Logout) => logout(&args).await,
+        Some(Command::Accounts) => accounts().await,
+        Some(Command::Whoami) => whoami(&args).await,
+        Some(Command::Repair) => repair(&args, &config).await,
+        Some(Command::Prepare) => prepare(&args, &config).await,
+        Some(Command::Info) => info(&args, &config).await,
+        Some(Command::Rollback) => rollback(&args, &config).await,
+        Some(Command::ClearOld) => clear_old(&args, &config).await,
+        Some(Command::Upgrade { ref version }) => {
+            upgrade(version, &args, &config).await
+        }
+        Some(Command::InstallFile {
+            ref file,
+            ref directory,
+        }) => install_file(file, directory, &args, &config).await,
+        Some(Command::ConfigSchema { ref output }) => {
+            config_schema(output.as_deref())
+        }
+        Some(Command::Batch { ref file }) => batch(file, &args, &config).await,
+        None => launch(&args, &config).await,
+    }
+}
 
-    let metadata =
-        get_metadata(&args.modpack_id, game_version.to_string().as_str())
-            .await?;
+async fn launch(args: &Args, config: &Config) -> Result<()> {
+    if args.list_versions {
+        let versions =
+            supported_game_versions(&args.modpack_id, config).await?;
+        println!("{}", versions.iter().format(", "));
+        return Ok(());
+    }
+
+    if args.plan {
+        return plan(args, config).await;
+    }
+
+    if args.server {
+        return install_server(args, config).await;
+    }
+
+    if args.verify_only {
+        return verify(args, config).await;
+    }
+
+    let operation = shutdown::start_operation().await;
+    let cancel = operation.token.clone();
+    tokio::spawn(cancel_on_ctrl_c(cancel.clone(), config.clone()));
+    let PreparedLaunch { base_path, cred } =
+        prepare_launch(args, config, &cancel).await?;
+    drop(operation);
+
+    if args.no_launch {
+        return Ok(());
+    }
+
+    if cancel.is_cancelled() {
+        return Err(UklientError::Cancelled);
+    }
+
+    // theseus can't pass arguments to the game, so this goes through
+    // options.txt, and is put back as it was once the game exits
+    let fullscreen_before = if args.fullscreen {
+        let previous = options::get_option(&base_path, "fullscreen")?;
+        options::set_option(&base_path, "fullscreen", "true")?;
+        Some(previous)
+    } else {
+        None
+    };
+
+    let result = match profile::run(&base_path, &cred).await {
+        Ok(process) => {
+            wait_for_game(
+                process,
+                config.launch_grace_period(),
+                args.game_output,
+            )
+            .await
+        }
+        Err(e) => Err(e.into()),
+    };
+
+    if let Some(previous) = fullscreen_before {
+        let restored = match previous {
+            Some(value) => {
+                options::set_option(&base_path, "fullscreen", &value)
+            }
+            None => options::unset_option(&base_path, "fullscreen"),
+        };
+        if let Err(e) = restored {
+            warn!("Could not turn fullscreen back off: {e}");
+        }
+    }
+
+    result?;
+    info!("Goodbye!");
+
+    Ok(())
+}
+
+/// Waits for the game to exit. Its stdio can't be chosen here: theseus'
+/// `profile::run` spawns the game and decides whether its output is
+/// inherited, and goes to the terminal no matter what, or piped. Piped
+/// output is always read until the game exits, so that the game never blocks
+/// on a full pipe, and is only shown if `show_output` is set. The end of
+/// piped stderr is what explains a game that exits early, i.e. within
+/// `grace_period` of starting
+async fn wait_for_game(
+    mut process: Child,
+    grace_period: Duration,
+    show_output: bool,
+) -> Result<()> {
+    if let Some(pid) = process.id() {
+        info!("PID: {pid}");
+    } else {
+        warn!("NO PID? no bitches");
+    }
+
+    if process.stdout.is_none() && !show_output {
+        debug!("The game inherited the terminal, its output can't be hidden");
+    }
+    let stdout = process.stdout.take().map(|stdout| {
+        tokio::spawn(read_game_output(stdout, show_output.then(io::stdout)))
+    });
+    let stderr = process.stderr.take().map(|stderr| {
+        tokio::spawn(read_game_output(stderr, show_output.then(io::stderr)))
+    });
+
+    // the jvm can die right away (bad arguments, missing libraries...)
+    let early_exit =
+        match tokio::time::timeout(grace_period, process.wait()).await {
+            Ok(status) => Some(status?).filter(|s| !s.success()),
+            Err(_) => None,
+        };
+
+    process.wait().await?;
+    if let Some(stdout) = stdout {
+        stdout.await.ok();
+    }
+    let stderr = match stderr {
+        Some(stderr) => stderr.await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    if let Some(status) = early_exit {
+        warn!("The game exited early ({status})");
+        let stderr = if stderr.is_empty() {
+            "its output was not captured, run with --game-output to see it"
+                .to_string()
+        } else {
+            stderr.join("\n")
+        };
+        return Err(UklientError::GameStartError(stderr));
+    }
+
+    Ok(())
+}
+
+/// Reads the game's `output` line by line, writing it to `echo` if set, and
+/// returns its last lines
+async fn read_game_output<R, W>(output: R, mut echo: Option<W>) -> Vec<String>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = io::BufReader::new(output).lines();
+    let mut tail = VecDeque::with_capacity(GAME_OUTPUT_TAIL);
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Some(echo) = &mut echo {
+            echo.write_all(format!("{line}\n").as_bytes()).await.ok();
+        }
+        if tail.len() == GAME_OUTPUT_TAIL {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+    tail.into()
+}
+
+/// Installs everything and logs in without launching, so that the game can
+/// be launched without a network connection later
+async fn prepare(args: &Args, config: &Config) -> Result<()> {
+    let operation = shutdown::start_operation().await;
+    tokio::spawn(cancel_on_ctrl_c(operation.token.clone(), config.clone()));
+    let PreparedLaunch { base_path, .. } =
+        prepare_launch(args, config, &operation.token).await?;
+    drop(operation);
+
+    let mut size = 0;
+    for dir in [base_path, paths::modpack_cache_dir(), paths::java_dir()] {
+        if dir.is_dir() {
+            size += fs_extra::dir::get_size(dir)?;
+        }
+    }
+    info!(
+        "Everything is ready to play offline, {} cached",
+        HumanBytes(size)
+    );
+
+    Ok(())
+}
+
+/// What launching the game needs, once everything is installed
+struct PreparedLaunch {
+    base_path: PathBuf,
+    cred: Credentials,
+}
+
+/// Does everything launching the game needs: installs the modpack and Java,
+/// registers the profile and logs in
+async fn prepare_launch(
+    args: &Args,
+    config: &Config,
+    cancel: &CancellationToken,
+) -> Result<PreparedLaunch> {
+    let game_version = resolve_game_version(args, config).await?;
+    check_game_version(&game_version).await?;
+
+    let mut metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
     debug!(
         "Found {} version {:?} on Minecraft {}",
         metadata.loader, metadata.loader_version, game_version
     );
 
-    // grr theseus
-    let fixed_name = &metadata.name.replace(' ', "_");
-    let base_path: PathBuf = HOME.join(".uklient").join(fixed_name);
-    tokio::fs::create_dir_all(&base_path).await?;
+    let base_path = instance_path(config, &metadata.name);
+    for dir in [&paths::data_dir(), &paths::cache_dir(), &base_path] {
+        check_writable(dir)?;
+    }
+
+    let java_version = java_version_for(
+        config,
+        &game_version,
+        &metadata.loader,
+        metadata.loader_version.as_ref(),
+    );
+    // neither needs the other, and both can have a lot to download
+    let game_version_name = game_version.to_string();
+    let (java, prefetched) = tokio::join!(
+        get_java_settings(
+            java_version,
+            args.force_java_download,
+            config,
+            cancel,
+        ),
+        modpack::prefetch_modpack(
+            &args.modpack_id,
+            &game_version_name,
+            config,
+            cancel
+        ),
+    );
+    let mut java = java?;
+    match prefetched {
+        Err(UklientError::Cancelled) => return Err(UklientError::Cancelled),
+        Err(e) => warn!("Could not download the modpack ahead of time: {e}"),
+        Ok(()) => {}
+    }
+
+    if let Some(name) = &config.profile_name {
+        metadata.name = name.clone();
+    }
+
+    if let Some(language) = &config.language {
+        options::set_option(&base_path, "lang", language)?;
+    }
+    let mut jvm_args = config.java_arguments.clone();
+    jvm_args.extend(read_instance_jvm_args(&base_path)?);
+    java.extra_arguments = (!jvm_args.is_empty()).then_some(jvm_args);
 
+    let instance = InstanceConfig::load(&base_path)?;
+    let mut resolution = config.resolution(&instance);
+    resolution.width = args.width.unwrap_or(resolution.width);
+    resolution.height = args.height.unwrap_or(resolution.height);
+    let loader = match &metadata.loader_version {
+        Some(version) => format!("{} {}", metadata.loader, version.id),
+        None => metadata.loader.to_string(),
+    };
+    let max_memory = config.max_memory(&instance);
+    system::check_memory(max_memory, config)?;
     let mc_profile = Profile {
         path: base_path.clone(),
         metadata,
         java: Some(java),
         memory: Some(MemorySettings {
-            maximum: (4 * 1024) as u32,
+            maximum: max_memory,
             ..MemorySettings::default()
         }),
-        resolution: Some(WindowSize(1280, 720)),
+        resolution: Some(WindowSize(resolution.width, resolution.height)),
         hooks: None,
     };
 
-    profile::add(mc_profile).await?;
-    let cred = connect_account().await?;
+    register_profile(mc_profile, args.reset_profile).await?;
+    let store: Box<dyn CredentialStore> = if args.no_save_credentials {
+        Box::new(ReadOnlyStore::new(FileStore::default()))
+    } else {
+        Box::new(FileStore::default())
+    };
+    let preferred = match &args.account {
+        Some(account) => Some(resolve_account(store.as_ref(), account).await?),
+        None => None,
+    };
+    let cred =
+        connect_account(store.as_ref(), preferred, !args.non_interactive)
+            .await?;
     info!("Connected account {}", cred.username);
 
-    modpack::install_modpack(
+    let options = InstallOptions {
+        skip_resourcepacks: args.no_resourcepacks,
+        interactive: is_interactive(args),
+        include_optional: args.include_optional,
+        optional_files: args.optional_files.clone(),
+        low_end: args.low_end,
+        side: Side::Client,
+        cancel: cancel.clone(),
+    };
+    let summary = install_instance(
         &base_path,
         &args.modpack_id,
+        &game_version,
+        config,
+        &options,
+    )
+    .await?;
+    info!(
+        "Successfully installed {} on Minecraft {game_version} with {loader} and Java {java_version}",
+        summary.version_name
+    );
+
+    Ok(PreparedLaunch { base_path, cred })
+}
+
+/// Installs the server side of the modpack in its own directory. Nothing
+/// client-only is done, not even logging in
+async fn install_server(args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    let base_path = instance_path(config, &format!("{} server", metadata.name));
+    check_writable(&base_path)?;
+
+    let options = InstallOptions {
+        skip_resourcepacks: true,
+        interactive: is_interactive(args),
+        include_optional: args.include_optional,
+        optional_files: args.optional_files.clone(),
+        low_end: args.low_end,
+        side: Side::Server,
+        cancel: CancellationToken::new(),
+    };
+    let summary = install_instance(
+        &base_path,
+        &args.modpack_id,
+        &game_version,
+        config,
+        &options,
+    )
+    .await?;
+    info!(
+        "Successfully installed the {} server in {}",
+        summary.version_name,
+        base_path.display()
+    );
+
+    Ok(())
+}
+
+/// Installs a modpack file in `directory`. Nothing is registered or
+/// launched, since the file doesn't say which loader it needs
+async fn install_file(
+    file: &Path,
+    directory: &Path,
+    args: &Args,
+    config: &Config,
+) -> Result<()> {
+    check_writable(directory)?;
+    let options = InstallOptions {
+        skip_resourcepacks: args.no_resourcepacks,
+        interactive: is_interactive(args),
+        include_optional: args.include_optional,
+        optional_files: args.optional_files.clone(),
+        low_end: args.low_end,
+        side: Side::Client,
+        cancel: CancellationToken::new(),
+    };
+    let summary =
+        modpack::install_modpack_from_file(directory, file, config, &options)
+            .await?;
+    info!(
+        "Successfully installed {} in {}",
+        summary.version_name,
+        directory.display()
+    );
+
+    Ok(())
+}
+
+/// Installs the modpack in `base_path` and reports what changed
+async fn install_instance(
+    base_path: &Path,
+    id: &str,
+    game_version: &MinecraftVersion,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<InstallSummary> {
+    let summary = modpack::install_modpack(
+        base_path,
+        id,
         game_version.to_string(),
+        config,
+        options,
     )
     .await?;
-    info!("Sucessfully installed modpack");
 
-    if args.no_launch {
+    let cleaned = &summary.cleaned;
+    if !cleaned.moved.is_empty() || !cleaned.deleted.is_empty() {
+        info!(
+            "Moved {} files to .old and deleted {} files",
+            cleaned.moved.len(),
+            cleaned.deleted.len()
+        );
+    }
+    if cleaned.pruned > 0 {
+        info!("Pruned {} files from older updates", cleaned.pruned);
+    }
+
+    if summary.updated {
+        match &summary.changelog {
+            Some(changelog) => {
+                info!("Changelog for {}:\n{changelog}", summary.version_name)
+            }
+            None => debug!("{} has no changelog", summary.version_name),
+        }
+    }
+
+    Ok(summary)
+}
+
+async fn upgrade(version: &str, args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    let new_version = if config.exact_game_version {
+        MinecraftVersion::parse(version)?
+    } else {
+        let supported =
+            supported_game_versions(&args.modpack_id, config).await?;
+        MinecraftVersion::parse_latest_patch(version, &supported)?
+    };
+    upgrade_version(
+        &instance_path(config, &metadata.name),
+        &new_version,
+        args,
+        config,
+    )
+    .await
+}
+
+/// Moves the install in `base_path` to `new_version`, with the modpack
+/// version, loader and Java it needs. Mods that don't belong to the new
+/// modpack version end up in `.old` like after any update. The instance is
+/// then renamed after the new modpack version and `new_version` is saved as
+/// the configured game version, so the next launch keeps using it
+async fn upgrade_version(
+    base_path: &Path,
+    new_version: &MinecraftVersion,
+    args: &Args,
+    config: &Config,
+) -> Result<()> {
+    let mut mc_profile = profile::get(base_path)
+        .await?
+        .ok_or_else(|| UklientError::NotInstalled(base_path.to_path_buf()))?;
+
+    check_game_version(new_version).await?;
+    let supported = supported_game_versions(&args.modpack_id, config).await?;
+    if !supported.contains(new_version) {
+        return Err(UklientError::UnsupportedGameVersion(
+            new_version.to_string(),
+        ));
+    }
+
+    let metadata = get_metadata(
+        &args.modpack_id,
+        new_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+    let old_version = mc_profile.metadata.game_version.clone();
+    // the next launch looks for the instance under the new modpack version
+    let new_path = instance_path(config, &metadata.name);
+    if new_path != base_path && new_path.try_exists()? {
+        return Err(UklientError::InstanceExists(new_path));
+    }
+
+    let java_version = java_version_for(
+        config,
+        new_version,
+        &metadata.loader,
+        metadata.loader_version.as_ref(),
+    );
+    let operation = shutdown::start_operation().await;
+    tokio::spawn(cancel_on_ctrl_c(operation.token.clone(), config.clone()));
+    let mut java = get_java_settings(
+        java_version,
+        args.force_java_download,
+        config,
+        &operation.token,
+    )
+    .await?;
+    java.extra_arguments =
+        mc_profile.java.and_then(|java| java.extra_arguments);
+
+    mc_profile.metadata.game_version = metadata.game_version;
+    mc_profile.metadata.loader = metadata.loader;
+    mc_profile.metadata.loader_version = metadata.loader_version;
+    mc_profile.java = Some(java);
+    register_profile(mc_profile, args.reset_profile).await?;
+
+    let options = InstallOptions {
+        skip_resourcepacks: args.no_resourcepacks,
+        interactive: is_interactive(args),
+        include_optional: args.include_optional,
+        optional_files: args.optional_files.clone(),
+        low_end: args.low_end,
+        side: Side::Client,
+        cancel: operation.token.clone(),
+    };
+    let summary = install_instance(
+        base_path,
+        &args.modpack_id,
+        new_version,
+        config,
+        &options,
+    )
+    .await?;
+    drop(operation);
+
+    if new_path != base_path {
+        move_instance(base_path, &new_path).await?;
+    }
+    Config::save_game_version(&new_version.to_string())?;
+    if std::env::var_os("UKLIENT_GAME_VERSION").is_some() {
+        warn!("UKLIENT_GAME_VERSION is set, it overrides the upgraded game version");
+    }
+
+    if new_path.join("config").is_dir() {
+        warn!("Mod configs were kept, some of them may not work on Minecraft {new_version}");
+    }
+    info!(
+        "Moved {} from Minecraft {old_version} to {new_version} with Java {java_version}",
+        summary.version_name
+    );
+
+    Ok(())
+}
+
+/// Installs every modpack listed in `file`, one after the other. A modpack
+/// that fails to install doesn't stop the others
+async fn batch(file: &Path, args: &Args, config: &Config) -> Result<()> {
+    let packs = read_batch_file(file, &config.game_version(&[])?)?;
+    let options = InstallOptions {
+        skip_resourcepacks: args.no_resourcepacks,
+        interactive: false,
+        include_optional: args.include_optional,
+        optional_files: args.optional_files.clone(),
+        low_end: args.low_end,
+        side: Side::Client,
+        cancel: CancellationToken::new(),
+    };
+
+    let mut failed = Vec::new();
+    for (id, game_version) in &packs {
+        info!("Installing {id} on Minecraft {game_version}");
+        let result = async {
+            let metadata =
+                get_metadata(id, game_version.to_string().as_str(), config)
+                    .await?;
+            let base_path = instance_path(config, &metadata.name);
+            check_writable(&base_path)?;
+            install_instance(&base_path, id, game_version, config, &options)
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(summary) => {
+                info!("Successfully installed {}", summary.version_name)
+            }
+            Err(e) => {
+                warn!("Could not install {id}: {e}");
+                failed.push((id, e));
+            }
+        }
+    }
+
+    info!(
+        "Installed {} out of {} modpacks",
+        packs.len() - failed.len(),
+        packs.len()
+    );
+    for (id, e) in &failed {
+        warn!("{id}: {e}");
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(UklientError::BatchError(failed.len()))
+    }
+}
+
+/// Reads the modpacks of a batch file, one `<modpack id> [minecraft version]`
+/// per line. Blank lines and lines starting with `#` are ignored, and
+/// `default_version` is used for lines without a version
+fn read_batch_file(
+    file: &Path,
+    default_version: &MinecraftVersion,
+) -> Result<Vec<(String, MinecraftVersion)>> {
+    let mut packs = Vec::new();
+    for line in std::fs::read_to_string(file)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let (Some(id), version, None) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(UklientError::BatchFileError(line.into()));
+        };
+        let version = match version {
+            Some(version) => MinecraftVersion::parse(version)?,
+            None => default_version.clone(),
+        };
+        packs.push((id.to_owned(), version));
+    }
+
+    Ok(packs)
+}
+
+/// Prints what launching would install and run. Nothing is downloaded or
+/// written, the modpack's files are only listed if it is already cached
+async fn plan(args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    let java_version = java_version_for(
+        config,
+        &game_version,
+        &metadata.loader,
+        metadata.loader_version.as_ref(),
+    );
+    let java = java_status(java_version, config).await;
+
+    let base_path = instance_path(config, &metadata.name);
+    let options = InstallOptions {
+        skip_resourcepacks: args.no_resourcepacks,
+        interactive: false,
+        include_optional: args.include_optional,
+        optional_files: args.optional_files.clone(),
+        low_end: args.low_end,
+        side: Side::Client,
+        cancel: CancellationToken::new(),
+    };
+    let modpack = modpack::plan_install(
+        &base_path,
+        &args.modpack_id,
+        &game_version.to_string(),
+        config,
+        &options,
+    )
+    .await?;
+
+    let plan = LaunchPlan {
+        game_version: game_version.to_string(),
+        java,
+        loader: metadata.loader.to_string(),
+        loader_version: metadata.loader_version.as_ref().map(|l| l.id.clone()),
+        loader_profile: metadata.loader_version.map(|l| l.url),
+        instance: base_path,
+        modpack,
+    };
+
+    match args.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+        Format::Text => print_plan(&plan),
+    }
+
+    Ok(())
+}
+
+fn print_java_status(java: &JavaStatus) {
+    match (&java.path, java.source) {
+        (Some(path), Some(source)) => {
+            println!("Java {}: {} ({source})", java.version, path.display())
+        }
+        _ => println!("Java {}: will be downloaded", java.version),
+    }
+}
+
+fn print_plan(plan: &LaunchPlan) {
+    println!("Minecraft: {}", plan.game_version);
+    print_java_status(&plan.java);
+    println!("Loader: {}", plan.loader);
+    if let Some(version) = &plan.loader_version {
+        println!("Loader version: {version}");
+    }
+    if let Some(url) = &plan.loader_profile {
+        println!("Loader profile: {url}");
+    }
+    println!("Modpack version: {}", plan.modpack.version_name);
+    println!("Instance: {}", plan.instance.display());
+    if !plan.modpack.resolved {
+        println!("Modpack files: unknown until the modpack is downloaded");
+    }
+
+    for (action, paths) in [
+        ("Download", &plan.modpack.download),
+        ("Install", &plan.modpack.install),
+        ("Remove", &plan.modpack.remove),
+    ] {
+        println!("{action} ({}):", paths.len());
+        for path in paths {
+            println!("  {}", path.display());
+        }
+    }
+}
+
+/// The configured game version. When it has no patch, the modpack's
+/// supported versions are fetched to pick the newest patch
+async fn resolve_game_version(
+    args: &Args,
+    config: &Config,
+) -> Result<MinecraftVersion> {
+    let supported = if config.needs_supported_versions() {
+        supported_game_versions(&args.modpack_id, config).await?
+    } else {
+        Vec::new()
+    };
+    config.game_version(&supported)
+}
+
+/// Makes sure Mojang knows about `game_version`, to catch typos before
+/// resolving anything else. Only releases are checked, and failing to fetch
+/// the known versions isn't fatal
+async fn check_game_version(game_version: &MinecraftVersion) -> Result<()> {
+    if let MinecraftVersion::Snapshot { .. } = game_version {
         return Ok(());
     }
 
-    let process = profile::run(&base_path, &cred).await?;
-    if let Some(pid) = process.id() {
-        info!("PID: {pid}");
+    match MinecraftVersion::fetch_known().await {
+        Ok(known) if !game_version.is_valid(&known) => {
+            Err(UklientError::UnknownGameVersion(game_version.to_string()))
+        }
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!("Could not check that Minecraft {game_version} exists: {e}");
+            Ok(())
+        }
+    }
+}
+
+/// The Java version to launch this Minecraft version with the
+/// `loader_version` build of `loader`. The config's `java_versions` can pick
+/// another one, as long as the loader build can run on it
+fn java_version_for(
+    config: &Config,
+    game_version: &MinecraftVersion,
+    loader: &ModLoader,
+    loader_version: Option<&LoaderVersion>,
+) -> u8 {
+    let java_version = config
+        .java_override(game_version)
+        .unwrap_or_else(|| game_version.required_java_major());
+    check_loader_java(
+        java_version,
+        loader,
+        loader_version.map(|l| l.id.as_str()),
+    )
+}
+
+/// The options an install with `args` used, to check what it installed.
+/// Nobody is asked about optional files, only the ones given on the command
+/// line count
+fn check_options(args: &Args, cancel: CancellationToken) -> InstallOptions {
+    InstallOptions {
+        skip_resourcepacks: args.no_resourcepacks,
+        interactive: false,
+        include_optional: args.include_optional,
+        optional_files: args.optional_files.clone(),
+        low_end: args.low_end,
+        side: Side::Client,
+        cancel,
+    }
+}
+
+/// Checks the installed modpack, failing if anything is missing or corrupt
+async fn verify(args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    let report = modpack::verify_install(
+        &instance_path(config, &metadata.name),
+        &args.modpack_id,
+        game_version.to_string(),
+        config,
+        &check_options(args, CancellationToken::new()),
+    )
+    .await?;
+
+    match args.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        Format::Text => {
+            for path in &report.missing {
+                println!("missing: {}", path.display());
+            }
+            for path in &report.mismatched {
+                println!("mismatched: {}", path.display());
+            }
+            for path in &report.missing_overrides {
+                println!("missing override: {}", path.display());
+            }
+        }
+    }
+
+    if report.is_ok() {
+        Ok(())
     } else {
-        warn!("NO PID? no bitches");
+        let broken = report.missing.len()
+            + report.mismatched.len()
+            + report.missing_overrides.len();
+        Err(UklientError::VerifyError(broken))
     }
+}
 
-    process.wait_with_output().await?;
-    info!("Goodbye!");
+async fn repair(args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    let operation = shutdown::start_operation().await;
+    tokio::spawn(cancel_on_ctrl_c(operation.token.clone(), config.clone()));
+    let report = modpack::repair(
+        &instance_path(config, &metadata.name),
+        &args.modpack_id,
+        game_version.to_string(),
+        config,
+        &check_options(args, operation.token.clone()),
+    )
+    .await?;
+    drop(operation);
+
+    if report.is_ok() {
+        info!("Nothing to repair");
+    } else {
+        for path in report.missing.iter().chain(&report.mismatched) {
+            info!("Repaired {}", path.display());
+        }
+        for path in &report.missing_overrides {
+            info!("Restored {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+async fn rollback(args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    let report = modpack::rollback(&instance_path(config, &metadata.name))?;
+    let Some(generation) = report.generation else {
+        info!("There is no update to roll back");
+        return Ok(());
+    };
+
+    for path in &report.removed {
+        info!("Removed {}", path.display());
+    }
+    for path in &report.restored {
+        info!("Restored {}", path.display());
+    }
+    info!("Rolled back the update from {generation}");
+
+    Ok(())
+}
+
+async fn info(args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    println!("Modpack: {}", metadata.name);
+    println!("Minecraft: {}", metadata.game_version);
+    println!("Loader: {}", metadata.loader);
+    if let Some(loader_version) = &metadata.loader_version {
+        println!("Loader version: {}", loader_version.id);
+        println!("Loader profile: {}", loader_version.url);
+    }
+    let base_path = instance_path(config, &metadata.name);
+    println!("Instance: {}", base_path.display());
+    if let Some(installed) = loader::detect_loader(&base_path).await {
+        println!("Installed loader: {installed}");
+    }
+
+    let java_version = java_version_for(
+        config,
+        &game_version,
+        &metadata.loader,
+        metadata.loader_version.as_ref(),
+    );
+    print_java_status(&java_status(java_version, config).await);
+
+    println!("Installed Java versions:");
+    for (version, path) in list_installed_javas().await {
+        println!("  Java {version}: {}", path.display());
+    }
 
     Ok(())
 }
 
-pub async fn get_latest_fabric(mc_version: &String) -> Result<LoaderVersion> {
-    let downloaded = daedalus::download_file(
-        format!("{FABRIC_META_URL}/versions/loader/{mc_version}").as_str(),
-        None,
+async fn clear_old(args: &Args, config: &Config) -> Result<()> {
+    let game_version = resolve_game_version(args, config).await?;
+    let metadata = get_metadata(
+        &args.modpack_id,
+        game_version.to_string().as_str(),
+        config,
+    )
+    .await?;
+
+    let old_dirs = modpack::old_dirs(&instance_path(config, &metadata.name))?;
+    let mut size = 0;
+    for dir in &old_dirs {
+        size += fs_extra::dir::get_size(dir)?;
+    }
+
+    if size == 0 {
+        info!("There are no old files to clear");
+        return Ok(());
+    }
+
+    if size > CLEAR_OLD_CONFIRM_SIZE && is_interactive(args) {
+        let confirmed = Confirm::new()
+            .with_prompt(format!("Delete {} of old files?", HumanBytes(size)))
+            .interact()?;
+        if !confirmed {
+            return Ok(());
+        }
+    }
+
+    for dir in &old_dirs {
+        std::fs::remove_dir_all(dir)?;
+        std::fs::create_dir(dir)?;
+    }
+    info!("Freed {}", HumanBytes(size));
+
+    Ok(())
+}
+
+/// Prints the JSON schema of `Config`, or writes it to `output`
+fn config_schema(output: Option<&Path>) -> Result<()> {
+    let schema = schemars::schema_for!(Config);
+    match output {
+        Some(path) => {
+            let file = File::create(path)?;
+            serde_json::to_writer_pretty(BufWriter::new(file), &schema)?;
+            info!("Wrote the config schema to {}", path.display());
+        }
+        None => println!("{}", serde_json::to_string_pretty(&schema)?),
+    }
+
+    Ok(())
+}
+
+/// The directory the modpack with this name is installed in
+fn instance_path(config: &Config, name: &str) -> PathBuf {
+    // grr theseus
+    config.instances_dir().join(name.replace(' ', "_"))
+}
+
+/// Makes sure files can be created in `path`, creating it if needed, so that
+/// permission problems show up before anything gets installed
+fn check_writable(path: &Path) -> Result<()> {
+    let probe = path.join(".uklient-write-test");
+    let check = || -> std::io::Result<()> {
+        std::fs::create_dir_all(path)?;
+        File::create(&probe)?;
+        std::fs::remove_file(&probe)
+    };
+
+    check().map_err(|e| UklientError::NotWritable(path.to_path_buf(), e))
+}
+
+async fn login() -> Result<()> {
+    let cred = add_account(&FileStore::default()).await?;
+    info!("Logged in as {}", cred.username);
+
+    Ok(())
+}
+
+/// Removes the account chosen with `--account`, or the last used one
+async fn logout(args: &Args) -> Result<()> {
+    let store = FileStore::default();
+    let id = match &args.account {
+        Some(account) => Some(resolve_account(&store, account).await?),
+        None => store.load(None).await?.map(|c| c.id),
+    };
+
+    match id {
+        Some(id) if remove_account(&store, id).await? => info!("Logged out"),
+        _ => info!("No account is logged in"),
+    }
+
+    Ok(())
+}
+
+/// Lists the saved accounts, the one used by default first
+async fn accounts() -> Result<()> {
+    let store = FileStore::default();
+    let last_used = store.load(None).await?.map(|c| c.id);
+    let accounts = list_accounts(&store).await?;
+    if accounts.is_empty() {
+        info!("No account is logged in");
+    }
+
+    for (id, username) in accounts
+        .into_iter()
+        .sorted_by_key(|(id, _)| Some(*id) != last_used)
+    {
+        let marker = if Some(id) == last_used { "*" } else { " " };
+        println!("{marker} {username} ({id})");
+    }
+
+    Ok(())
+}
+
+async fn whoami(args: &Args) -> Result<()> {
+    let store = FileStore::default();
+    let preferred = match &args.account {
+        Some(account) => Some(resolve_account(&store, account).await?),
+        None => None,
+    };
+    let mut creds = store
+        .load(preferred)
+        .await?
+        .ok_or(UklientError::NotLoggedIn)?;
+
+    if needs_refresh(&creds) {
+        creds = refresh_credentials(creds).await?;
+        store.save(&creds).await?;
+    }
+
+    let expires = creds.expires.with_timezone(&Local);
+    println!("Username: {}", creds.username);
+    println!("UUID: {}", creds.id);
+    println!("Expires: {}", expires.format("%Y-%m-%d %H:%M:%S"));
+
+    Ok(())
+}
+
+/// Registers the profile in theseus, replacing the existing one if it changed
+/// Renames the instance in `base_path` to `new_path` and registers its
+/// profile there instead
+async fn move_instance(base_path: &Path, new_path: &Path) -> Result<()> {
+    let mut mc_profile = profile::get(base_path)
+        .await?
+        .ok_or_else(|| UklientError::NotInstalled(base_path.to_path_buf()))?;
+    tokio::fs::rename(base_path, new_path).await?;
+    profile::remove(base_path).await?;
+
+    mc_profile.path = new_path.to_path_buf();
+    register_profile(mc_profile, false).await?;
+    debug!("Moved the instance to {}", new_path.display());
+
+    Ok(())
+}
+
+async fn register_profile(mc_profile: Profile, reset: bool) -> Result<()> {
+    let existing = match profile::get(&mc_profile.path).await {
+        Ok(existing) => existing,
+        Err(e) if reset => {
+            warn!("Could not read the existing profile, resetting it: {e}");
+            profile::remove(&mc_profile.path).await?;
+            None
+        }
+        Err(e) => {
+            warn!("Could not read the existing profile, run with --reset-profile to reset it");
+            return Err(e.into());
+        }
+    };
+
+    match existing {
+        Some(existing)
+            if serde_json::to_value(&existing)?
+                == serde_json::to_value(&mc_profile)? =>
+        {
+            debug!("Profile is already registered");
+        }
+        Some(_) => {
+            debug!("Updating the registered profile");
+            profile::remove(&mc_profile.path).await?;
+            profile::add(mc_profile).await?;
+        }
+        None => {
+            profile::add(mc_profile).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancels `token` when Ctrl+C is pressed. If it is pressed again, stops
+/// everything, cleans up and exits
+async fn cancel_on_ctrl_c(token: CancellationToken, config: Config) {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        warn!("Cancelling, press Ctrl+C again to stop everything and quit");
+        token.cancel();
+    }
+
+    if tokio::signal::ctrl_c().await.is_ok() {
+        let report = shutdown::stop_everything(&config).await;
+        if !report.stopped {
+            warn!("Some operations were still running when cleaning up");
+        }
+        info!("Removed {} unfinished files", report.removed.len());
+        std::process::exit(130);
+    }
+}
+
+/// Downloads the loader metadata at `url`, or reads it from the cache entry
+/// `key` if it was downloaded recently. A stale entry is used if the
+/// download fails, so that launching works while the meta server is down
+async fn download_meta(url: &str, key: &str) -> Result<Vec<u8>> {
+    let path = paths::meta_cache_dir().join(format!("{key}.json"));
+    let fresh = std::fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map_or(false, |age| age < META_CACHE_TTL);
+    if fresh {
+        if let Ok(cached) = std::fs::read(&path) {
+            debug!("Using the cached {key} metadata");
+            return Ok(cached);
+        }
+    }
+
+    match daedalus::download_file(url, None).await {
+        Ok(downloaded) => {
+            let cached = std::fs::create_dir_all(paths::meta_cache_dir())
+                .and_then(|_| std::fs::write(&path, &downloaded));
+            if let Err(e) = cached {
+                warn!("Could not cache the {key} metadata: {e}");
+            }
+            Ok(downloaded.to_vec())
+        }
+        Err(e) => match std::fs::read(&path) {
+            Ok(cached) => {
+                warn!("Could not download the {key} metadata, using an older copy: {e}");
+                Ok(cached)
+            }
+            Err(_) => Err(e.into()),
+        },
+    }
+}
+
+/// The Fabric loader build `loader_version`, or the latest one if unset
+pub async fn get_fabric(
+    mc_version: &String,
+    loader_version: Option<&str>,
+) -> Result<LoaderVersion> {
+    let downloaded = download_meta(
+        &format!("{FABRIC_META_URL}/versions/loader/{mc_version}"),
+        &format!("fabric-{mc_version}"),
     )
     .await?;
 
     let versions: Vec<LoaderVersionElement> =
         serde_json::from_slice(&downloaded)?;
-    let latest = versions.get(0).ok_or(MetaError("fabric"))?.loader.clone();
+    let latest = select_loader(&versions, loader_version, "fabric")?;
     let manifest_url = format!(
         "{}/versions/loader/{}/{}/profile/json",
         FABRIC_META_URL, mc_version, latest.version
@@ -143,16 +1544,20 @@ pub async fn get_latest_fabric(mc_version: &String) -> Result<LoaderVersion> {
     })
 }
 
-pub async fn get_latest_quilt(mc_version: &String) -> Result<LoaderVersion> {
-    let downloaded = daedalus::download_file(
-        format!("{QUILT_META_URL}/versions/loader/{mc_version}").as_str(),
-        None,
+/// The Quilt loader build `loader_version`, or the latest one if unset
+pub async fn get_quilt(
+    mc_version: &String,
+    loader_version: Option<&str>,
+) -> Result<LoaderVersion> {
+    let downloaded = download_meta(
+        &format!("{QUILT_META_URL}/versions/loader/{mc_version}"),
+        &format!("quilt-{mc_version}"),
     )
     .await?;
 
     let versions: Vec<LoaderVersionElement> =
         serde_json::from_slice(&downloaded)?;
-    let latest = versions.get(0).ok_or(MetaError("quilt"))?.loader.clone();
+    let latest = select_loader(&versions, loader_version, "quilt")?;
     let manifest_url = format!(
         "{}/versions/loader/{}/{}/profile/json",
         QUILT_META_URL, mc_version, latest.version
@@ -165,37 +1570,203 @@ pub async fn get_latest_quilt(mc_version: &String) -> Result<LoaderVersion> {
     })
 }
 
-async fn connect_account() -> Result<Credentials> {
-    let credentials_path = Path::new("./credentials.json");
+/// Picks the `loader_version` build from `versions`, or the newest one in
+/// that range if it is one, or the first one (the latest) if unset
+fn select_loader(
+    versions: &[LoaderVersionElement],
+    loader_version: Option<&str>,
+    loader: &'static str,
+) -> Result<MetaLoaderVersion> {
+    let Some(wanted) = loader_version else {
+        return versions
+            .get(0)
+            .map(|v| v.loader.clone())
+            .ok_or(MetaError(loader));
+    };
 
-    if credentials_path.try_exists()? {
-        let credentials: Result<Credentials> = {
-            let file = File::open(credentials_path)?;
-            let creds: Credentials =
-                serde_json::from_reader(BufReader::new(file))?;
+    if let Some(exact) = versions.iter().find(|v| v.loader.version == wanted) {
+        return Ok(exact.loader.clone());
+    }
 
-            refresh_credentials(creds).await
-        };
+    // the versions are sorted from the newest to the oldest
+    let range = LoaderVersionRange::parse(wanted)?;
+    versions
+        .iter()
+        .find(|v| range.matches(&v.loader.version))
+        .map(|v| v.loader.clone())
+        .ok_or_else(|| UklientError::NoMatchingLoaderVersion {
+            loader,
+            wanted: wanted.to_string(),
+        })
+}
 
-        if let Ok(creds) = credentials {
+/// The recommended Forge build for `mc_version`, or the latest one if none
+/// is recommended yet
+pub async fn get_latest_forge(mc_version: &String) -> Result<LoaderVersion> {
+    let downloaded = download_meta(FORGE_PROMOTIONS_URL, "forge").await?;
+
+    let promotions: ForgePromotions = serde_json::from_slice(&downloaded)?;
+    let recommended =
+        promotions.promos.get(&format!("{mc_version}-recommended"));
+    let (build, stable) = match recommended {
+        Some(build) => (build, true),
+        None => (
+            promotions
+                .promos
+                .get(&format!("{mc_version}-latest"))
+                .ok_or(MetaError("forge"))?,
+            false,
+        ),
+    };
+    let id = format!("{mc_version}-{build}");
+    let manifest_url = format!("{FORGE_META_URL}/versions/{id}.json");
+
+    Ok(LoaderVersion {
+        id,
+        stable,
+        url: manifest_url,
+    })
+}
+
+/// The newest stable NeoForge build for `mc_version`, or the newest beta if
+/// there is no stable one yet. Only Minecraft 1.20.2 and later are covered,
+/// NeoForge for 1.20.1 is published as Forge
+pub async fn get_latest_neoforge(mc_version: &String) -> Result<LoaderVersion> {
+    let game_version = MinecraftVersion::parse(mc_version)?;
+    let downloaded =
+        download_meta(NEOFORGE_MAVEN_METADATA_URL, "neoforge").await?;
+
+    let metadata = String::from_utf8_lossy(&downloaded);
+    let id = latest_neoforge_build(&metadata, &game_version)
+        .ok_or(MetaError("neoforge"))?;
+    let manifest_url = format!("{NEOFORGE_META_URL}/versions/{id}.json");
+
+    Ok(LoaderVersion {
+        stable: !id.contains('-'),
+        id,
+        url: manifest_url,
+    })
+}
+
+/// Picks the NeoForge build for `game_version` from the versions listed in
+/// the maven `metadata`. Builds are numbered after the Minecraft version they
+/// are for: `20.4.80-beta` is the 80th build for 1.20.4
+fn latest_neoforge_build(
+    metadata: &str,
+    game_version: &MinecraftVersion,
+) -> Option<String> {
+    let MinecraftVersion::Release { minor, patch } = game_version else {
+        return None;
+    };
+    let prefix = format!("{minor}.{patch}.");
+
+    metadata
+        .split("<version>")
+        .skip(1)
+        .filter_map(|s| s.split_once("</version>").map(|(v, _)| v.trim()))
+        .filter_map(|version| {
+            let rest = version.strip_prefix(&prefix)?;
+            let (build, stable) = match rest.split_once('-') {
+                Some((build, _)) => (build, false),
+                None => (rest, true),
+            };
+            Some(((stable, build.parse::<u32>().ok()?), version))
+        })
+        .max_by_key(|(key, _)| *key)
+        .map(|(_, version)| version.to_string())
+}
+
+/// Logs into the `preferred` account saved in `store`, or the last used one,
+/// refreshing it if it is about to expire, or into a new one that is then
+/// saved there. The account that is used becomes the last used one. If
+/// `interactive_reauth` isn't set, an error is returned instead of asking the
+/// user to log in again
+async fn connect_account(
+    store: &dyn CredentialStore,
+    preferred: Option<Uuid>,
+    interactive_reauth: bool,
+) -> Result<Credentials> {
+    let saved = store.load(preferred).await?;
+    let logged_in = saved.is_some();
+    if let Some(creds) = saved {
+        if !needs_refresh(&creds) {
+            debug!("The saved account is still valid, not refreshing it");
+            // makes it the last used account, even if it wasn't refreshed
+            store.save(&creds).await?;
             return Ok(creds);
         }
+
+        match refresh_credentials(creds).await {
+            Ok(creds) => {
+                store.save(&creds).await?;
+                return Ok(creds);
+            }
+            Err(e) => warn!("Could not refresh the saved account: {e}"),
+        }
     }
 
-    let scopes = vec!["XboxLive.signin", "offline_access"];
-    let code = get_device_code(scopes).await?;
+    if !interactive_reauth {
+        return Err(if logged_in {
+            UklientError::SessionExpired
+        } else {
+            UklientError::NotLoggedIn
+        });
+    }
+
+    warn!("No account was found");
+    add_account(store).await
+}
+
+/// Logs into a new account with the device code flow, and saves it in
+/// `store` as the last used account
+async fn add_account(store: &dyn CredentialStore) -> Result<Credentials> {
+    let code = get_device_code(DEFAULT_SCOPES.to_vec()).await?;
     warn!(
-        "No account was found, please go to {} and enter the code {}",
+        "Please go to {} and enter the code {}",
         code.verification_uri, code.user_code
     );
 
-    let creds = get_credentials(code.device_code).await?;
-    let file = File::create(credentials_path)?;
-    serde_json::to_writer(BufWriter::new(file), &creds)?;
+    let creds = get_credentials(&code).await?;
+    store.save(&creds).await?;
 
     Ok(creds)
 }
 
+/// The UUID and username of every account saved in `store`
+async fn list_accounts(
+    store: &dyn CredentialStore,
+) -> Result<Vec<(Uuid, String)>> {
+    Ok(store
+        .list()
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c.username))
+        .sorted_by(|a, b| a.1.cmp(&b.1))
+        .collect())
+}
+
+/// Forgets the account `id`, returning whether it was saved
+async fn remove_account(store: &dyn CredentialStore, id: Uuid) -> Result<bool> {
+    store.delete(id).await
+}
+
+/// Finds the saved account `account` refers to, by UUID or by username
+async fn resolve_account(
+    store: &dyn CredentialStore,
+    account: &str,
+) -> Result<Uuid> {
+    if let Ok(id) = Uuid::parse_str(account) {
+        return Ok(id);
+    }
+
+    list_accounts(store)
+        .await?
+        .into_iter()
+        .find(|(_, username)| username.eq_ignore_ascii_case(account))
+        .map(|(id, _)| id)
+        .ok_or_else(|| UklientError::UnknownAccount(account.to_string()))
+}
+
 #[derive(Error, Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum UklientError {
@@ -215,16 +1786,37 @@ pub enum UklientError {
     DaedalusError(#[from] daedalus::Error),
     #[error("json error: {0}")]
     JsonError(#[from] serde_json::Error),
+    #[error("config error: {0}")]
+    TomlError(#[from] toml::de::Error),
+    #[error("invalid config: {0}")]
+    ConfigError(String),
     #[error("libium error: {0}")]
     LibiumError(#[from] libium::upgrade::Error),
     #[error("libium modpack error: {0}")]
     LibiumModpackError(#[from] libium::upgrade::modpack_downloadable::Error),
     #[error("ferinth error: {0}")]
     FerinthError(#[from] ferinth::Error),
+    #[error("curseforge error: {0}")]
+    CurseForgeError(String),
+    #[error("{0:?} {1}")]
+    ModpackFileError(PathBuf, &'static str),
     #[error("zip error")]
     ZipError,
+    #[error("could not extract {phase} to {path:?}")]
+    ExtractError { phase: &'static str, path: PathBuf },
+    #[error("max_memory is {max_memory} MB but only {safe} of the {total} MB of memory can be used, lower it or set memory_check")]
+    TooMuchMemory {
+        max_memory: u32,
+        total: u64,
+        safe: u64,
+    },
     #[error("{0} version not found")]
     MetaError(&'static str),
+    #[error("no {loader} version matches {wanted}")]
+    NoMatchingLoaderVersion {
+        loader: &'static str,
+        wanted: String,
+    },
     #[error("unknown type: {0:?}")]
     UnknownTypeError(OsString),
     #[error("tokio acquire error: {0}")]
@@ -233,12 +1825,59 @@ pub enum UklientError {
     ReqwestError(#[from] reqwest::Error),
     #[error("java not found")]
     JavaNotFoundError,
+    #[error("no vendor has a build of java {0} for this platform")]
+    NoJavaBuild(u8),
+    #[error("java did not report its version in time")]
+    JavaVersionTimeout,
+    #[error("32-bit java is not supported")]
+    Java32BitError,
     #[error("minecraft version error: {0}")]
     VersionError(#[from] crate::version::VersionError),
+    #[error("{0} files are missing or corrupt")]
+    VerifyError(usize),
+    #[error("{file} doesn't match its expected checksum")]
+    ChecksumMismatch { file: String },
+    #[error("the modpack doesn't support minecraft {0}")]
+    UnsupportedGameVersion(String),
+    #[error("nothing is installed in {0:?}")]
+    NotInstalled(PathBuf),
+    #[error("an instance already exists in {0:?}")]
+    InstanceExists(PathBuf),
+    #[error("minecraft {0} doesn't exist")]
+    UnknownGameVersion(String),
     #[error("url parse error: {0}")]
     UrlParseError(#[from] url::ParseError),
     #[error("login error: {0}")]
     LoginError(String),
+    #[error("the login code expired, please log in again")]
+    AuthExpired,
+    #[error("the login was declined")]
+    AuthDeclined,
+    #[error(
+        "this account has no minecraft license, buy the game or, if it comes \
+         with game pass, start the official launcher once to create a profile"
+    )]
+    NoMinecraftOwnership,
+    #[error("couldn't fetch the minecraft profile: {0}")]
+    ProfileError(reqwest::Error),
+    #[error("the session expired, please log in again")]
+    SessionExpired,
+    #[error("no account is logged in")]
+    NotLoggedIn,
+    #[error("no saved account is named {0}")]
+    UnknownAccount(String),
+    #[error("{0:?} is not writable: {1}")]
+    NotWritable(PathBuf, std::io::Error),
+    #[error("invalid line in the batch file: {0}")]
+    BatchFileError(String),
+    #[error("{0} modpacks could not be installed")]
+    BatchError(usize),
+    #[error("java signature error: {0}")]
+    SignatureError(String),
+    #[error("cancelled")]
+    Cancelled,
+    #[error("the game failed to start: {0}")]
+    GameStartError(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -250,6 +1889,13 @@ struct GameVersion {
     pub stable: bool,
 }
 
+/// The builds Forge recommends, keyed by `<minecraft version>-recommended`
+/// or `<minecraft version>-latest`
+#[derive(Deserialize, Debug, Clone)]
+struct ForgePromotions {
+    pub promos: HashMap<String, String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct LoaderVersionElement {
     pub loader: MetaLoaderVersion,
@@ -269,3 +1915,91 @@ struct MetaLoaderVersion {
     #[serde(default = "bool::default")]
     pub stable: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEOFORGE_METADATA: &str = "<metadata><versioning><versions>
+        <version>20.2.3-beta</version>
+        <version>20.2.88</version>
+        <version>20.4.9-beta</version>
+        <version>20.4.80-beta</version>
+        <version>20.4.237</version>
+        <version>20.4.240</version>
+        <version>20.6.2-beta</version>
+    </versions></versioning></metadata>";
+
+    #[test]
+    fn loader_builds_can_raise_the_java_version() {
+        let neoforge = LoaderVersion {
+            id: "20.4.80-beta".into(),
+            stable: false,
+            url: String::new(),
+        };
+        let game_version = MinecraftVersion::new(16, 5);
+        let java_version = |loader_version| {
+            java_version_for(
+                &Config::default(),
+                &game_version,
+                &ModLoader::Forge,
+                loader_version,
+            )
+        };
+        assert_eq!(java_version(None), 8);
+        assert_eq!(java_version(Some(&neoforge)), 17);
+    }
+
+    #[test]
+    fn java_overrides_are_raised_to_the_loader_floor() {
+        let forge = LoaderVersion {
+            id: "1.18.2-40.2.0".into(),
+            stable: true,
+            url: String::new(),
+        };
+        let config = Config {
+            java_versions: HashMap::from([
+                ("1.18".into(), 8),
+                ("*".into(), 21),
+            ]),
+            ..Config::default()
+        };
+        let java_version = |game_version| {
+            java_version_for(
+                &config,
+                &game_version,
+                &ModLoader::Forge,
+                Some(&forge),
+            )
+        };
+        assert_eq!(java_version(MinecraftVersion::new(18, 2)), 17);
+        assert_eq!(java_version(MinecraftVersion::new(19, 2)), 21);
+    }
+
+    #[test]
+    fn neoforge_prefers_the_newest_stable_build() {
+        let build = latest_neoforge_build(
+            NEOFORGE_METADATA,
+            &MinecraftVersion::new(20, 4),
+        );
+        assert_eq!(build.as_deref(), Some("20.4.240"));
+    }
+
+    #[test]
+    fn neoforge_falls_back_to_betas() {
+        let build = latest_neoforge_build(
+            NEOFORGE_METADATA,
+            &MinecraftVersion::new(20, 6),
+        );
+        assert_eq!(build.as_deref(), Some("20.6.2-beta"));
+    }
+
+    #[test]
+    fn neoforge_has_no_build_for_other_versions() {
+        let build = latest_neoforge_build(
+            NEOFORGE_METADATA,
+            &MinecraftVersion::new(20, 1),
+        );
+        assert_eq!(build, None);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{paths, Result};
+use libium::HOME;
+use std::ffi::OsStr;
+use std::path::Path;
+use theseus::profile;
+use tracing::{debug, info};
+
+/// Moves everything older versions of uklient left in `~/.config/uklient`,
+/// `~/.uklient` and the working directory to where it goes now. Anything
+/// that was already moved is left alone, so this can run on every start
+pub async fn migrate() -> Result<()> {
+    let old_dir = HOME.join(".config").join("uklient");
+
+    move_path(
+        &old_dir.join("config.toml"),
+        &paths::config_dir().join("config.toml"),
+    )?;
+    move_path(&old_dir.join("gnupg"), &paths::gnupg_dir())?;
+    move_path(Path::new("credentials.json"), &paths::credentials_path())?;
+    move_children(&old_dir.join(".cache"), &paths::modpack_cache_dir())?;
+
+    if let Ok(entries) = old_dir.read_dir() {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with("jdk") {
+                let target = paths::java_dir().join(entry.file_name());
+                move_path(&entry.path(), &target)?;
+            }
+        }
+    }
+
+    // only ever held extracted modpacks and partial downloads, which are
+    // kept so that they can be resumed
+    let old_tmp = old_dir.join(".tmp");
+    if old_tmp.is_dir() {
+        for entry in old_tmp.read_dir()?.flatten() {
+            let path = entry.path();
+            if path.is_file() && path.extension() == Some(OsStr::new("part")) {
+                move_path(&path, &paths::tmp_dir().join(entry.file_name()))?;
+            }
+        }
+        std::fs::remove_dir_all(&old_tmp)?;
+        debug!("Deleted {}", old_tmp.display());
+    }
+
+    migrate_instances(&HOME.join(".uklient")).await
+}
+
+/// Moves the instances in `old_dir`, unregistering their old profiles.
+/// Directories that aren't registered profiles are left where they are
+async fn migrate_instances(old_dir: &Path) -> Result<()> {
+    let Ok(entries) = old_dir.read_dir() else {
+        return Ok(());
+    };
+
+    for entry in entries.flatten() {
+        let old_path = entry.path();
+        if !matches!(profile::get(&old_path).await, Ok(Some(_))) {
+            continue;
+        }
+
+        let target = paths::instances_dir().join(entry.file_name());
+        if move_path(&old_path, &target)? {
+            // it gets registered again at its new path on the next launch
+            profile::remove(&old_path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves every file and directory in `from` into `to`
+fn move_children(from: &Path, to: &Path) -> Result<()> {
+    let Ok(entries) = from.read_dir() else {
+        return Ok(());
+    };
+
+    for entry in entries.flatten() {
+        move_path(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Moves `from` to `to`, unless there is nothing to move or something is
+/// already at `to`. Returns whether anything was moved
+fn move_path(from: &Path, to: &Path) -> Result<bool> {
+    if from == to || !from.exists() || to.exists() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // renaming doesn't work across filesystems
+    if std::fs::rename(from, to).is_err() {
+        if from.is_dir() {
+            let mut options = fs_extra::dir::CopyOptions::new();
+            options.content_only = true;
+            std::fs::create_dir_all(to)?;
+            fs_extra::dir::copy(from, to, &options)?;
+            std::fs::remove_dir_all(from)?;
+        } else {
+            fs_extra::file::move_file(
+                from,
+                to,
+                &fs_extra::file::CopyOptions::new(),
+            )?;
+        }
+    }
+
+    info!("Moved {} to {}", from.display(), to.display());
+    Ok(true)
+}
@@ -1,20 +1,38 @@
-use crate::UklientError::{MetaError, UnknownTypeError, ZipError};
+use crate::config::{Channel, Config, ExtraMod};
+use crate::curseforge::{self, CurseForgeFile};
+use crate::network::RetryPolicy;
+use crate::paths;
+use crate::shutdown;
+use crate::system;
+use crate::version::MinecraftVersion;
+use crate::UklientError::{
+    Cancelled, ChecksumMismatch, ExtractError, MetaError, ModpackFileError,
+    UnknownTypeError, ZipError,
+};
 use crate::{
-    get_latest_fabric, get_latest_quilt, Result, UklientError, CLIENT,
+    byte_progress_bar, get_fabric, get_latest_forge, get_latest_neoforge,
+    get_quilt, Result, CLIENT, PROGRESS, STYLE_FILES,
 };
+use chrono::Utc;
 use daedalus::modded::LoaderVersion;
-use ferinth::Ferinth;
+use dialoguer::MultiSelect;
+use ferinth::structures::project::ProjectSupportRange;
+use ferinth::structures::version::Version;
 use fs_extra::{
     dir::{copy as copy_dir, CopyOptions as DirCopyOptions},
     file::{move_file, CopyOptions as FileCopyOptions},
 };
+use indicatif::{HumanBytes, ProgressBar};
 use itertools::Itertools;
 use libium::modpack::extract_zip;
 use libium::modpack::modrinth::deser_metadata;
 use libium::modpack::modrinth::read_metadata_file;
+use libium::modpack::modrinth::structs::ModpackFile;
 use libium::upgrade::Downloadable;
 use libium::version_ext::VersionExt;
-use libium::HOME;
+use serde::Serialize;
+use sha2::{Digest, Sha512};
+use std::cmp::Reverse;
 use std::fs::File;
 use std::{
     ffi::OsString,
@@ -27,30 +45,44 @@ use tokio::{
     fs::{copy, create_dir_all, remove_file},
     sync::Semaphore,
     task::JoinSet,
+    time::sleep,
 };
-use tracing::{info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use url::Url;
+use zip::ZipArchive;
+
+const MODRINTH_CDN: &str = "cdn.modrinth.com";
+/// Lists the files an update downloaded, in the `.old` generation it created
+const ADDED_FILES_LIST: &str = ".added";
 
 pub async fn get_metadata(
     id: &str,
     game_version: &str,
+    config: &Config,
 ) -> Result<ProfileMetadata> {
-    let modrinth = Ferinth::default();
+    let modrinth = config.modrinth()?;
 
     let info = modrinth.get_project(id).await?;
-    let versions = modrinth
-        .list_versions_filtered(id, None, Some(&[game_version]), None)
-        .await?;
+    let versions = modrinth.list_versions(id).await?;
 
-    if let Some(meta) = versions.first() {
+    if let Some(meta) =
+        select_version(&versions, game_version, config.release_channel)
+    {
+        // packs that only contain overrides (shaders, resource packs...)
+        // don't need a loader and can run on vanilla
         let loader_info = match meta.loaders.first() {
-            Some(l) => LoaderInfo::from(l, &game_version.into()).await?,
-            None => return Err(MetaError("loader")),
+            Some(l) => {
+                let pinned = config.loader_version.as_deref();
+                LoaderInfo::from(l, &game_version.into(), pinned).await?
+            }
+            None => LoaderInfo::VANILLA,
         };
 
         Ok(ProfileMetadata {
             name: format!("{}-{}", info.title, meta.name),
             loader: loader_info.loader,
-            loader_version: Some(loader_info.version),
+            loader_version: loader_info.version,
             game_version: game_version.into(),
             format_version: 1,
             icon: None,
@@ -60,109 +92,968 @@ pub async fn get_metadata(
     }
 }
 
+/// Picks the newest modpack version for `game_version` that is allowed by
+/// `channel`, or the newest one from any channel if there is none
+fn select_version<'a>(
+    versions: &'a [Version],
+    game_version: &str,
+    channel: Channel,
+) -> Option<&'a Version> {
+    let matching = versions
+        .iter()
+        .filter(|v| v.game_versions.iter().any(|g| g == game_version))
+        .collect::<Vec<_>>();
+
+    if let Some(version) = matching
+        .iter()
+        .copied()
+        .find(|v| channel.allows(&v.version_type))
+    {
+        return Some(version);
+    }
+
+    let fallback = matching.first().copied();
+    if let Some(version) = fallback {
+        warn!(
+            "No {channel:?} version of the modpack was found, using {} instead",
+            version.name
+        );
+    }
+    fallback
+}
+
+/// Lists every Minecraft release supported by at least one version of the
+/// modpack, sorted from oldest to newest
+pub async fn supported_game_versions(
+    id: &str,
+    config: &Config,
+) -> Result<Vec<MinecraftVersion>> {
+    let modrinth = config.modrinth()?;
+
+    let mut supported = Vec::new();
+    for version in modrinth.list_versions(id).await? {
+        for game_version in version.game_versions {
+            match MinecraftVersion::parse(&game_version) {
+                Ok(v @ MinecraftVersion::Release { .. }) => supported.push(v),
+                Ok(_) => debug!("Skipping snapshot {game_version}"),
+                Err(e) => debug!("Skipping game version {game_version}: {e}"),
+            }
+        }
+    }
+
+    supported.sort();
+    supported.dedup();
+    Ok(supported)
+}
+
 #[derive(Debug)]
 struct LoaderInfo {
     loader: ModLoader,
-    version: LoaderVersion,
+    /// The loader's version, `None` for vanilla
+    version: Option<LoaderVersion>,
 }
 
 impl LoaderInfo {
-    async fn from(value: &str, game_version: &String) -> Result<Self> {
+    const VANILLA: Self = Self {
+        loader: ModLoader::Vanilla,
+        version: None,
+    };
+
+    /// Resolves the `value` loader, using the `pinned` version if it is set
+    async fn from(
+        value: &str,
+        game_version: &String,
+        pinned: Option<&str>,
+    ) -> Result<Self> {
         match value.to_ascii_lowercase().as_str() {
             "fabric" => Ok(Self {
                 loader: ModLoader::Fabric,
-                version: get_latest_fabric(game_version).await?,
+                version: Some(get_fabric(game_version, pinned).await?),
             }),
             "quilt" => Ok(Self {
                 loader: ModLoader::Quilt,
-                version: get_latest_quilt(game_version).await?,
+                version: Some(get_quilt(game_version, pinned).await?),
             }),
+            "forge" => {
+                if let Some(pinned) = pinned {
+                    warn!("Forge versions can't be pinned, ignoring {pinned}");
+                }
+                Ok(Self {
+                    loader: ModLoader::Forge,
+                    version: Some(get_latest_forge(game_version).await?),
+                })
+            }
+            // theseus has no NeoForge loader, but NeoForge profiles are
+            // installed and launched the same way as Forge ones
+            "neoforge" => {
+                if let Some(pinned) = pinned {
+                    warn!(
+                        "NeoForge versions can't be pinned, ignoring {pinned}"
+                    );
+                }
+                Ok(Self {
+                    loader: ModLoader::Forge,
+                    version: Some(get_latest_neoforge(game_version).await?),
+                })
+            }
+            "minecraft" | "vanilla" => Ok(Self::VANILLA),
             _ => Err(MetaError("unknown loader")),
         }
     }
 }
 
-// code BLATANTLY stolen from ferium
+/// What `install_modpack` ended up doing
+#[derive(Debug, Clone)]
+pub struct InstallSummary {
+    /// The name of the installed modpack version
+    pub version_name: String,
+    /// The changelog of the installed modpack version, if it has one
+    pub changelog: Option<String>,
+    /// Whether anything was downloaded or installed
+    pub updated: bool,
+    /// The files that were taken out of the instance
+    pub cleaned: CleanedFiles,
+}
 
-pub async fn install_modpack(
-    output_dir: &Path,
+/// Files that were removed from a directory by `clean`
+#[derive(Debug, Clone, Default)]
+pub struct CleanedFiles {
+    /// Files that were moved to the `.old` directory, at their new location
+    pub moved: Vec<PathBuf>,
+    /// Files that were deleted outright
+    pub deleted: Vec<PathBuf>,
+    /// How many files from older generations were deleted from `.old`
+    pub pruned: usize,
+}
+
+/// Options that only apply to a single `install_modpack` run
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// Don't download or clean resource packs
+    pub skip_resourcepacks: bool,
+    /// Whether the user can be prompted, e.g. to choose optional files
+    pub interactive: bool,
+    /// Install every optional file without asking
+    pub include_optional: bool,
+    /// Optional files installed without asking, by path (`mods/foo.jar`) or
+    /// file name
+    pub optional_files: Vec<String>,
+    /// Leave out the mods listed in the config's `low_end_excluded`
+    pub low_end: bool,
+    /// The side the modpack is installed for
+    pub side: Side,
+    /// Stops the downloads when cancelled
+    pub cancel: CancellationToken,
+}
+
+/// The side of the game a modpack is installed for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Side {
+    #[default]
+    Client,
+    Server,
+}
+
+impl Side {
+    /// How much this side supports the file, if the modpack says so
+    fn support(self, file: &ModpackFile) -> Option<&ProjectSupportRange> {
+        file.env.as_ref().map(|env| match self {
+            Side::Client => &env.client,
+            Side::Server => &env.server,
+        })
+    }
+
+    /// The overrides that only apply to this side
+    fn overrides_dir(self) -> &'static str {
+        match self {
+            Side::Client => "client-overrides",
+            Side::Server => "server-overrides",
+        }
+    }
+}
+
+/// The format a modpack archive is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModpackSource {
+    Modrinth,
+    CurseForge,
+}
+
+impl ModpackSource {
+    /// Tells the format of `file` from the index file it contains
+    fn detect(file: &File) -> Result<Self> {
+        let archive = ZipArchive::new(file).map_err(|_| ZipError)?;
+        let has = |name: &str| archive.file_names().any(|f| f == name);
+
+        if has("modrinth.index.json") {
+            Ok(Self::Modrinth)
+        } else if has("manifest.json") {
+            Ok(Self::CurseForge)
+        } else {
+            Err(MetaError("modpack index"))
+        }
+    }
+}
+
+/// A modpack version that was downloaded and extracted
+struct FetchedModpack {
+    version: Version,
+    contents: ModpackContents,
+}
+
+/// What a modpack contains
+struct ModpackContents {
+    /// The files listed in a Modrinth modpack's index
+    files: Vec<ModpackFile>,
+    /// The files of a CurseForge modpack. They have no SHA512 to verify
+    curseforge_files: Vec<PackFile>,
+    /// The overrides for both sides, by name and where they are read from
+    overrides: Vec<(OsString, PathBuf)>,
+    /// The overrides that only apply to the client
+    client_overrides: Vec<(OsString, PathBuf)>,
+    /// The overrides that only apply to the server
+    server_overrides: Vec<(OsString, PathBuf)>,
+}
+
+impl ModpackContents {
+    /// Takes the overrides for `side`, the side specific ones last so they
+    /// win over the common ones
+    fn take_overrides(&mut self, side: Side) -> Vec<(OsString, PathBuf)> {
+        let mut overrides = std::mem::take(&mut self.overrides);
+        overrides.extend(std::mem::take(match side {
+            Side::Client => &mut self.client_overrides,
+            Side::Server => &mut self.server_overrides,
+        }));
+        overrides
+    }
+}
+
+/// Downloads the modpack version for `game_version` to the cache, unless it
+/// already is there, and extracts it
+async fn fetch_modpack(
     id: &str,
-    game_version: String,
+    game_version: &str,
+    config: &Config,
+) -> Result<FetchedModpack> {
+    let (version, modpack_path) =
+        download_modpack(id, game_version, config).await?;
+
+    Ok(FetchedModpack {
+        version,
+        contents: extract_modpack(&modpack_path, config).await?,
+    })
+}
+
+/// Downloads the modpack version for `game_version` to the cache ahead of
+/// time, so that installing it later doesn't have to. Stops as soon as
+/// `cancel` is cancelled
+pub async fn prefetch_modpack(
+    id: &str,
+    game_version: &str,
+    config: &Config,
+    cancel: &CancellationToken,
 ) -> Result<()> {
-    let modrinth = Ferinth::default();
+    tokio::select! {
+        result = download_modpack(id, game_version, config) => result?,
+        _ = cancel.cancelled() => return Err(Cancelled),
+    };
+    Ok(())
+}
 
-    let version = modrinth
-        .list_versions(id)
-        .await?
-        .iter()
-        .find(|v| v.game_versions.contains(&game_version))
-        .ok_or(MetaError("modpack"))?
-        .clone();
+/// Looks up the modpack version for `game_version` on Modrinth
+async fn find_modpack_version(
+    id: &str,
+    game_version: &str,
+    config: &Config,
+) -> Result<Version> {
+    let modrinth = config.modrinth()?;
+
+    let versions = modrinth.list_versions(id).await?;
+    let version =
+        select_version(&versions, game_version, config.release_channel)
+            .ok_or(MetaError("modpack"))?
+            .clone();
 
     info!("Found modpack version {}", version.name);
+    Ok(version)
+}
 
-    let mut version_file: Downloadable = version.into_version_file().into();
+/// The file of the modpack `version`, and where it is in the cache
+fn cached_modpack(version: &Version) -> (Downloadable, PathBuf) {
+    let mut version_file: Downloadable =
+        version.clone().into_version_file().into();
     version_file.output = version_file.filename().into();
 
-    let cache_dir = HOME.join(".config").join("uklient").join(".cache");
+    let modpack_path = paths::modpack_cache_dir().join(&version_file.output);
+    (version_file, modpack_path)
+}
+
+/// Downloads the modpack version for `game_version` to the cache, unless it
+/// already is there, and returns where it is
+async fn download_modpack(
+    id: &str,
+    game_version: &str,
+    config: &Config,
+) -> Result<(Version, PathBuf)> {
+    let version = find_modpack_version(id, game_version, config).await?;
+    let (mut version_file, modpack_path) = cached_modpack(&version);
+
+    let cache_dir = paths::modpack_cache_dir();
     create_dir_all(&cache_dir).await?;
 
-    let modpack_path = cache_dir.join(&version_file.output);
     if !modpack_path.exists() {
-        version_file.download(&CLIENT, &cache_dir, |_| {}).await?;
+        apply_mirror(
+            &mut version_file.download_url,
+            config.network.modrinth_mirror.as_deref(),
+        )?;
+        let progress_bar =
+            PROGRESS.add(byte_progress_bar(version_file.length as u64, config));
+        version_file
+            .download(&CLIENT, &cache_dir, |n| progress_bar.inc(n as u64))
+            .await?;
+        progress_bar.finish_and_clear();
     }
 
+    Ok((version, modpack_path))
+}
+
+/// The index of a modpack archive, whichever format it is in
+struct ModpackIndex {
+    name: String,
+    files: Vec<ModpackFile>,
+    curseforge_files: Vec<PackFile>,
+    /// The directory of the archive the overrides are in
+    overrides: String,
+}
+
+/// Reads the index of `modpack_file`. The files of CurseForge modpacks are
+/// looked up, but nothing is downloaded
+async fn read_index(
+    modpack_file: &File,
+    config: &Config,
+) -> Result<ModpackIndex> {
+    match ModpackSource::detect(modpack_file)? {
+        ModpackSource::Modrinth => {
+            let metadata = deser_metadata(
+                &read_metadata_file(modpack_file).map_err(|_| ZipError)?,
+            )?;
+            Ok(ModpackIndex {
+                name: metadata.name,
+                files: metadata.files,
+                curseforge_files: Vec::new(),
+                overrides: "overrides".into(),
+            })
+        }
+        ModpackSource::CurseForge => {
+            let manifest = curseforge::read_manifest(modpack_file)?;
+            let files = curseforge::resolve_files(
+                &manifest.files,
+                config.curseforge_api_key.as_deref(),
+            )
+            .await?
+            .into_iter()
+            .map(PackFile::from)
+            .collect();
+            Ok(ModpackIndex {
+                name: manifest.name,
+                files: Vec::new(),
+                curseforge_files: files,
+                overrides: manifest.overrides,
+            })
+        }
+    }
+}
+
+/// Reads the index of the modpack at `modpack_path` and lists its overrides
+/// without extracting it. The overrides point inside the archive, so this is
+/// only good to know what an install would do
+async fn read_modpack(
+    modpack_path: &Path,
+    config: &Config,
+) -> Result<ModpackContents> {
     let modpack_file = File::open(modpack_path)?;
-    let metadata = deser_metadata(
-        &read_metadata_file(&modpack_file).map_err(|_| ZipError)?,
-    )?;
+    let index = read_index(&modpack_file, config).await?;
 
-    let tmp_dir = HOME
-        .join(".config")
-        .join("uklient")
-        .join(".tmp")
-        .join(metadata.name);
-    extract_zip(modpack_file, &tmp_dir)
-        .await
-        .map_err(|_| ZipError)?;
-    let overrides = read_overrides(&tmp_dir.join("overrides"))?;
-
-    let mut to_download: Vec<Downloadable> = Vec::new();
-    for file in metadata.files {
-        to_download.push(file.into());
+    let archive = ZipArchive::new(&modpack_file).map_err(|_| ZipError)?;
+    let list = |dir: &str| archive_overrides(archive.file_names(), dir);
+    Ok(ModpackContents {
+        overrides: list(&index.overrides),
+        client_overrides: list(Side::Client.overrides_dir()),
+        server_overrides: list(Side::Server.overrides_dir()),
+        files: index.files,
+        curseforge_files: index.curseforge_files,
+    })
+}
+
+/// The overrides in the `dir` directory of an archive with the files
+/// `names`, like `read_overrides` would find them once extracted
+fn archive_overrides<'a>(
+    names: impl Iterator<Item = &'a str>,
+    dir: &str,
+) -> Vec<(OsString, PathBuf)> {
+    names
+        .filter_map(|name| {
+            let path = name.strip_prefix(dir)?.strip_prefix('/')?;
+            path.split('/').next().filter(|first| !first.is_empty())
+        })
+        .unique()
+        .map(|first| (first.into(), Path::new(dir).join(first)))
+        .collect()
+}
+
+/// Reads the index of the modpack at `modpack_path`, whichever format it is
+/// in, and extracts it
+async fn extract_modpack(
+    modpack_path: &Path,
+    config: &Config,
+) -> Result<ModpackContents> {
+    let modpack_file = File::open(modpack_path)?;
+    let ModpackIndex {
+        name,
+        files,
+        curseforge_files,
+        overrides,
+    } = read_index(&modpack_file, config).await?;
+
+    let tmp_root = config.tmp_dir();
+    crate::check_writable(&tmp_root)?;
+    check_extraction_space(&modpack_file, &tmp_root);
+    let tmp_dir = tmp_root.join(tmp_dir_name(&name));
+    if extract_zip(modpack_file, &tmp_dir).await.is_err() {
+        if tmp_dir.exists() {
+            std::fs::remove_dir_all(&tmp_dir)?;
+        }
+        return Err(ExtractError {
+            phase: "the modpack",
+            path: tmp_dir,
+        });
+    }
+
+    let side_overrides = |side: Side| {
+        let directory = tmp_dir.join(side.overrides_dir());
+        if directory.is_dir() {
+            read_overrides(&directory)
+        } else {
+            Ok(Vec::new())
+        }
+    };
+    Ok(ModpackContents {
+        overrides: read_overrides(&tmp_dir.join(overrides))?,
+        client_overrides: side_overrides(Side::Client)?,
+        server_overrides: side_overrides(Side::Server)?,
+        files,
+        curseforge_files,
+    })
+}
+
+/// A single path component made from a modpack's `name`, so that the
+/// directory it is extracted to, and deleted if that fails, can't be outside
+/// of the tmp directory
+fn tmp_dir_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = name.trim_matches(|c| c == '.' || c == ' ');
+
+    if name.is_empty() {
+        "modpack".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Warns if `tmp_root` doesn't look like it has room for the extracted
+/// `modpack_file`, which is likely with small RAM disks
+fn check_extraction_space(modpack_file: &File, tmp_root: &Path) {
+    let Ok(mut archive) = ZipArchive::new(modpack_file) else {
+        return;
+    };
+    let size: u64 = (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok().map(|f| f.size()))
+        .sum();
+
+    if let Some(available) = system::available_space(tmp_root) {
+        if available < size {
+            warn!(
+                "{} only has {} free, but the modpack needs {} to be extracted",
+                tmp_root.display(),
+                HumanBytes(available),
+                HumanBytes(size)
+            );
+        }
     }
+}
 
-    clean(&output_dir.join("mods"), &mut to_download, &mut Vec::new()).await?;
-    clean(
-        &output_dir.join("resourcepacks"),
-        &mut to_download,
-        &mut Vec::new(),
+/// What installing a modpack version would do
+struct PreparedInstall {
+    version_name: String,
+    changelog: Option<String>,
+    to_download: Vec<PackFile>,
+    overrides: Vec<(OsString, PathBuf)>,
+    /// Installed files that aren't part of the modpack anymore
+    stale: Vec<PathBuf>,
+    /// The directories that were checked for stale files
+    managed_dirs: Vec<PathBuf>,
+    pack_is_empty: bool,
+}
+
+/// Everything `install_modpack` does before touching the instance
+async fn prepare_install(
+    output_dir: &Path,
+    id: &str,
+    game_version: &str,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<PreparedInstall> {
+    let FetchedModpack { version, contents } =
+        fetch_modpack(id, game_version, config).await?;
+    let changelog = version.changelog.filter(|c| !c.trim().is_empty());
+
+    prepare_contents(
+        output_dir,
+        contents,
+        version.name,
+        changelog,
+        config,
+        options,
     )
-    .await?;
+}
 
-    if to_download.is_empty() && overrides.is_empty() {
-        info!("Everything is up to date!");
-        Ok(())
+/// What installing an extracted modpack with some options puts in the
+/// instance, whatever is already there
+struct SelectedContents {
+    to_download: Vec<PackFile>,
+    overrides: Vec<(OsString, PathBuf)>,
+    /// Whether the pack has no files besides its overrides
+    overrides_only: bool,
+    pack_is_empty: bool,
+}
+
+/// Works out which files and overrides of the modpack `options` install, along with the config's extra mods
+fn select_contents(
+    mut contents: ModpackContents,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<SelectedContents> {
+    let mut overrides = contents.take_overrides(options.side);
+
+    // packs without any files only ship overrides, and don't manage mods
+    let overrides_only =
+        contents.files.is_empty() && contents.curseforge_files.is_empty();
+    let pack_is_empty = overrides_only && overrides.is_empty();
+    let extra_mods = config
+        .extra_mods
+        .iter()
+        .map(PackFile::extra)
+        .collect::<Result<Vec<_>>>()?;
+    let (files, extra_mods) =
+        select_files(contents.files, extra_mods, options)?;
+    let mut to_download: Vec<PackFile> = Vec::new();
+    for file in files {
+        to_download.push(file.into());
+    }
+    to_download.extend(contents.curseforge_files);
+    // they go through the same cleaning, so they aren't moved to .old
+    to_download.extend(extra_mods);
+    remove_dupes(&mut to_download);
+    if options.low_end {
+        remove_low_end_excluded(&mut to_download, &config.low_end_excluded);
+    }
+
+    if options.skip_resourcepacks {
+        to_download
+            .retain(|f| !f.downloadable.output.starts_with("resourcepacks"));
+        overrides.retain(|(name, _)| name.as_os_str() != "resourcepacks");
+    }
+
+    Ok(SelectedContents {
+        to_download,
+        overrides,
+        overrides_only,
+        pack_is_empty,
+    })
+}
+
+/// Works out what installing the extracted modpack in `output_dir` would do
+fn prepare_contents(
+    output_dir: &Path,
+    contents: ModpackContents,
+    version_name: String,
+    changelog: Option<String>,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<PreparedInstall> {
+    let SelectedContents {
+        mut to_download,
+        overrides,
+        overrides_only,
+        pack_is_empty,
+    } = select_contents(contents, config, options)?;
+
+    let mut managed_dirs = Vec::new();
+    if overrides_only {
+        debug!("The modpack only contains overrides, leaving mods alone");
     } else {
-        download(output_dir.into(), to_download, overrides).await
+        managed_dirs.push(output_dir.join("mods"));
+    }
+    if !options.skip_resourcepacks {
+        managed_dirs.push(output_dir.join("resourcepacks"));
+    }
+
+    let mut stale = Vec::new();
+    for directory in &managed_dirs {
+        stale.extend(find_stale(directory, &mut to_download, &mut Vec::new())?);
     }
+
+    Ok(PreparedInstall {
+        version_name,
+        changelog,
+        to_download,
+        overrides,
+        stale,
+        managed_dirs,
+        pack_is_empty,
+    })
 }
 
-fn read_overrides(directory: &Path) -> Result<Vec<(OsString, PathBuf)>> {
-    let mut to_install = Vec::new();
-    for file in read_dir(directory)? {
-        let file = file?;
-        to_install.push((file.file_name(), file.path()));
+// code BLATANTLY stolen from ferium
+
+pub async fn install_modpack(
+    output_dir: &Path,
+    id: &str,
+    game_version: String,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<InstallSummary> {
+    let prepared =
+        prepare_install(output_dir, id, &game_version, config, options).await?;
+    apply_install(output_dir, prepared, config, &options.cancel).await
+}
+
+/// Installs the modpack at `modpack_path` in `output_dir`, without looking
+/// it up on Modrinth. The file name is used as the version name
+pub async fn install_modpack_from_file(
+    output_dir: &Path,
+    modpack_path: &Path,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<InstallSummary> {
+    if !modpack_path.is_file() {
+        return Err(ModpackFileError(modpack_path.into(), "doesn't exist"));
     }
-    Ok(to_install)
+    if ZipArchive::new(File::open(modpack_path)?).is_err() {
+        return Err(ModpackFileError(modpack_path.into(), "isn't a zip file"));
+    }
+
+    let contents = extract_modpack(modpack_path, config).await?;
+    let version_name = modpack_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let prepared = prepare_contents(
+        output_dir,
+        contents,
+        version_name,
+        None,
+        config,
+        options,
+    )?;
+    apply_install(output_dir, prepared, config, &options.cancel).await
 }
 
-async fn clean(
-    directory: &Path,
-    to_download: &mut Vec<Downloadable>,
-    to_install: &mut Vec<(OsString, PathBuf)>,
-) -> Result<()> {
-    let dupes = find_dupes_by_key(to_download, Downloadable::filename);
+/// Moves the stale files out of `output_dir`, then downloads and installs
+/// everything that is missing, unless `cancel` is cancelled first
+async fn apply_install(
+    output_dir: &Path,
+    prepared: PreparedInstall,
+    config: &Config,
+    cancel: &CancellationToken,
+) -> Result<InstallSummary> {
+    let PreparedInstall {
+        version_name,
+        changelog,
+        to_download,
+        overrides,
+        stale,
+        managed_dirs,
+        pack_is_empty,
+    } = prepared;
+
+    for directory in &managed_dirs {
+        create_dir_all(directory.join(".old")).await?;
+    }
+    let generation = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut cleaned = clean(stale, &generation).await?;
+    for directory in &managed_dirs {
+        let added = to_download
+            .iter()
+            .filter(|f| {
+                output_dir.join(&f.downloadable.output).parent()
+                    == Some(directory)
+            })
+            .map(PackFile::filename)
+            .collect_vec();
+        if !added.is_empty() {
+            let generation_dir = directory.join(".old").join(&generation);
+            create_dir_all(&generation_dir).await?;
+            std::fs::write(
+                generation_dir.join(ADDED_FILES_LIST),
+                added.join("\n"),
+            )?;
+        }
+    }
+
+    // the overrides replace files in place, they are backed up next to the
+    // instance in the same generation
+    let mut backup_dirs = managed_dirs;
+    if backup_overrides(output_dir, &overrides, &generation)? {
+        backup_dirs.push(output_dir.to_path_buf());
+    }
+
+    let updated = !(to_download.is_empty() && overrides.is_empty());
+    if updated {
+        let result =
+            download(output_dir.into(), to_download, overrides, config, cancel)
+                .await;
+        if let Err(e) = result {
+            warn!("The install failed, putting the previous files back");
+            for directory in &backup_dirs {
+                let mut report = RollbackReport::default();
+                if let Err(e) =
+                    restore_generation(directory, &generation, &mut report)
+                {
+                    warn!("Could not restore {}: {e}", directory.display());
+                }
+            }
+            return Err(e);
+        }
+    } else if pack_is_empty {
+        warn!("The modpack doesn't contain any files or overrides");
+    } else {
+        info!("Everything is up to date!");
+    }
+
+    // only once the install went through, in case it has to be undone
+    if !config.keep_old {
+        for directory in &backup_dirs {
+            cleaned.pruned +=
+                prune_old(&directory.join(".old"), config.old_generations())?;
+        }
+    }
+
+    Ok(InstallSummary {
+        version_name,
+        changelog,
+        updated,
+        cleaned,
+    })
+}
+
+/// What `install_modpack` would do, without doing any of it
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallPlan {
+    /// The name of the modpack version that would be installed
+    pub version_name: String,
+    /// The files that would be downloaded
+    pub download: Vec<PathBuf>,
+    /// The overrides that would be installed
+    pub install: Vec<PathBuf>,
+    /// The files that would be moved to `.old` or deleted
+    pub remove: Vec<PathBuf>,
+    /// Whether the modpack's files are known. They aren't until the modpack
+    /// is in the cache, the lists then only hold the extra mods
+    pub resolved: bool,
+}
+
+/// Works out what `install_modpack` would do, without downloading or
+/// writing anything. The index is read from the cached modpack if there is
+/// one, otherwise only the Modrinth version is looked up
+pub async fn plan_install(
+    output_dir: &Path,
+    id: &str,
+    game_version: &str,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<InstallPlan> {
+    let version = find_modpack_version(id, game_version, config).await?;
+    let (_, modpack_path) = cached_modpack(&version);
+    if !modpack_path.is_file() {
+        let download = config
+            .extra_mods
+            .iter()
+            .map(|extra| Ok(PackFile::extra(extra)?.downloadable.output))
+            .collect::<Result<_>>()?;
+        return Ok(InstallPlan {
+            version_name: version.name,
+            download,
+            install: Vec::new(),
+            remove: Vec::new(),
+            resolved: false,
+        });
+    }
+
+    let contents = read_modpack(&modpack_path, config).await?;
+    let prepared = prepare_contents(
+        output_dir,
+        contents,
+        version.name,
+        None,
+        config,
+        options,
+    )?;
+
+    Ok(InstallPlan {
+        version_name: prepared.version_name,
+        download: prepared
+            .to_download
+            .into_iter()
+            .map(|f| f.downloadable.output)
+            .collect(),
+        install: prepared
+            .overrides
+            .into_iter()
+            .map(|(name, _)| name.into())
+            .collect(),
+        remove: prepared.stale,
+        resolved: true,
+    })
+}
+
+/// The files of the index and the extra mods that are installed for
+/// `options.side`: the files the side doesn't support are left out, and the
+/// optional ones are chosen by `select_optional_files`
+fn select_files(
+    mut files: Vec<ModpackFile>,
+    extra_mods: Vec<PackFile>,
+    options: &InstallOptions,
+) -> Result<(Vec<ModpackFile>, Vec<PackFile>)> {
+    files.retain(|f| {
+        !matches!(
+            options.side.support(f),
+            Some(ProjectSupportRange::Unsupported)
+        )
+    });
+    select_optional_files(files, extra_mods, options.side, options)
+}
+
+/// Lets the user choose which optional files and extra mods get installed,
+/// unless they were already chosen: all of them with `include_optional`, or
+/// the optional files in `optional_files`. When running non-interactively,
+/// the other optional files are skipped and the extra mods are kept
+fn select_optional_files(
+    files: Vec<ModpackFile>,
+    extra_mods: Vec<PackFile>,
+    side: Side,
+    options: &InstallOptions,
+) -> Result<(Vec<ModpackFile>, Vec<PackFile>)> {
+    let (optional, mut selected): (Vec<_>, Vec<_>) =
+        files.into_iter().partition(|f| is_optional(f, side));
+    if (optional.is_empty() && extra_mods.is_empty())
+        || options.include_optional
+    {
+        selected.extend(optional);
+        return Ok((selected, extra_mods));
+    }
+    if !options.interactive || !options.optional_files.is_empty() {
+        let (chosen, skipped): (Vec<_>, Vec<_>) =
+            optional.into_iter().partition(|f| {
+                options.optional_files.iter().any(|wanted| {
+                    f.path == Path::new(wanted)
+                        || f.path.file_name()
+                            == Some(std::ffi::OsStr::new(wanted))
+                })
+            });
+        debug!("Skipping {} optional files", skipped.len());
+        selected.extend(chosen);
+        return Ok((selected, extra_mods));
+    }
+
+    // the extra mods come after the optional files in the list
+    let first_extra = optional.len();
+    let names = optional
+        .iter()
+        .map(|f| f.path.to_string_lossy().into_owned())
+        .chain(extra_mods.iter().map(|f| {
+            format!("{} (extra mod)", f.downloadable.output.display())
+        }))
+        .collect::<Vec<_>>();
+    let chosen = MultiSelect::new()
+        .with_prompt("Select the optional files to install")
+        .items(&names)
+        .defaults(&vec![true; names.len()])
+        .interact()?;
+
+    selected.extend(
+        optional
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| chosen.contains(i))
+            .map(|(_, f)| f),
+    );
+    let extra_mods = extra_mods
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| chosen.contains(&(first_extra + i)))
+        .map(|(_, f)| f)
+        .collect();
+    Ok((selected, extra_mods))
+}
+
+fn is_optional(file: &ModpackFile, side: Side) -> bool {
+    matches!(side.support(file), Some(ProjectSupportRange::Optional))
+}
+
+/// Removes the files of the `excluded` Modrinth projects
+fn remove_low_end_excluded(
+    to_download: &mut Vec<PackFile>,
+    excluded: &[String],
+) {
+    if excluded.is_empty() {
+        warn!("low_end_excluded is empty, no mods are left out");
+        return;
+    }
+
+    let (removed, kept): (Vec<_>, Vec<_>) =
+        std::mem::take(to_download).into_iter().partition(|f| {
+            modrinth_project_id(&f.downloadable.download_url)
+                .map_or(false, |id| excluded.iter().any(|e| e == id))
+        });
+    *to_download = kept;
+
+    if !removed.is_empty() {
+        warn!(
+            "Leaving out {} for low-end machines",
+            removed.iter().map(PackFile::filename).format(", ")
+        );
+    }
+}
+
+/// The ID of the project a file on Modrinth's CDN belongs to, from its
+/// `/data/<project id>/versions/...` URL
+fn modrinth_project_id(url: &Url) -> Option<&str> {
+    if url.host_str() != Some(MODRINTH_CDN) {
+        return None;
+    }
+
+    let mut segments = url.path_segments()?;
+    match (segments.next(), segments.next()) {
+        (Some("data"), Some(id)) => Some(id),
+        _ => None,
+    }
+}
+
+/// Removes files that would be downloaded more than once, regardless of the
+/// directory they end up in
+fn remove_dupes(to_download: &mut Vec<PackFile>) {
+    let dupes = find_dupes_by_key(to_download, PackFile::filename);
     if !dupes.is_empty() {
         warn!(
             "{} duplicate files were found {}. Remove the mod it belongs to",
@@ -173,7 +1064,351 @@ async fn clean(
                 .format(", ")
         );
     }
-    create_dir_all(directory.join(".old")).await?;
+}
+
+/// A file from the modpack index, along with every URL it can be downloaded
+/// from
+struct PackFile {
+    downloadable: Downloadable,
+    /// URLs to try when downloading from the main one fails
+    alternates: Vec<Url>,
+    /// The size declared in the index, in bytes
+    size: usize,
+    /// The hash the downloaded file must have, if it is known
+    sha512: Option<String>,
+    /// Whether failing to download the file shouldn't fail the install
+    best_effort: bool,
+}
+
+impl PackFile {
+    fn filename(&self) -> String {
+        self.downloadable.filename()
+    }
+
+    /// A mod from the config's `extra_mods`, downloaded on a best effort
+    /// basis
+    fn extra(extra: &ExtraMod) -> Result<Self> {
+        let url = Url::parse(&extra.url)?;
+        let filename =
+            extra.filename().ok_or(MetaError("extra mod filename"))?;
+
+        Ok(Self {
+            downloadable: Downloadable {
+                download_url: url,
+                output: Path::new("mods").join(filename),
+                length: 0,
+            },
+            alternates: Vec::new(),
+            size: 0,
+            sha512: extra.sha512.clone(),
+            best_effort: true,
+        })
+    }
+
+    /// Downloads the file to `output_dir`, unless it is a best effort file
+    /// that can't be downloaded. `progress` is advanced as bytes come in
+    async fn download(
+        self,
+        output_dir: &Path,
+        progress: &ProgressBar,
+        retry: RetryPolicy,
+    ) -> Result<()> {
+        let best_effort = self.best_effort;
+        let filename = self.filename();
+
+        match self.try_download(output_dir, progress, retry).await {
+            Err(e) if best_effort => {
+                warn!("Could not download {filename}, skipping it: {e}");
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Downloads the file to `output_dir`, moving on to the next URL every
+    /// time a download fails, after waiting as `retry` says. Every URL is
+    /// tried at least once
+    async fn try_download(
+        mut self,
+        output_dir: &Path,
+        progress: &ProgressBar,
+        retry: RetryPolicy,
+    ) -> Result<()> {
+        let mut urls = vec![self.downloadable.download_url.clone()];
+        urls.append(&mut self.alternates);
+        // where libium writes the file until it is complete
+        let _part = shutdown::track(
+            output_dir
+                .join(&self.downloadable.output)
+                .with_extension("part"),
+        );
+        let attempts = (retry.retries + 1).max(urls.len());
+
+        for (attempt, url) in urls.iter().cycle().take(attempts).enumerate() {
+            if attempt > 0 {
+                sleep(retry.delay(attempt - 1)).await;
+            }
+            self.downloadable.download_url = url.clone();
+            progress.set_position(0);
+            match self
+                .downloadable
+                .download(&CLIENT, output_dir, |n| progress.inc(n as u64))
+                .await
+            {
+                Ok(_) => break,
+                Err(e) if attempt + 1 < attempts => warn!(
+                    "Could not download {} from {url}, retrying: {e}",
+                    self.filename()
+                ),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if let Some(expected) = &self.sha512 {
+            let path = output_dir.join(&self.downloadable.output);
+            if sha512_file(&path)? != expected.to_ascii_lowercase() {
+                remove_file(&path).await?;
+                return Err(ChecksumMismatch {
+                    file: self.filename(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<CurseForgeFile> for PackFile {
+    fn from(file: CurseForgeFile) -> Self {
+        Self {
+            downloadable: Downloadable {
+                download_url: file.download_url,
+                output: Path::new(file.directory).join(&file.file_name),
+                length: file.file_length,
+            },
+            alternates: Vec::new(),
+            size: file.file_length,
+            sha512: None,
+            best_effort: false,
+        }
+    }
+}
+
+impl From<ModpackFile> for PackFile {
+    fn from(file: ModpackFile) -> Self {
+        let alternates = file.downloads.iter().skip(1).cloned().collect();
+        Self {
+            size: file.file_size,
+            alternates,
+            sha512: Some(file.hashes.sha512.clone()),
+            best_effort: false,
+            downloadable: file.into(),
+        }
+    }
+}
+
+/// The result of checking an installed modpack against its index
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// Files the install would download that aren't installed
+    pub missing: Vec<PathBuf>,
+    /// Installed files whose hash or size doesn't match
+    pub mismatched: Vec<PathBuf>,
+    /// Override files that aren't installed
+    pub missing_overrides: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+            && self.mismatched.is_empty()
+            && self.missing_overrides.is_empty()
+    }
+}
+
+/// Checks an installed modpack against its index, without changing anything.
+/// Only what installing it with `options` would install is checked
+pub async fn verify_install(
+    output_dir: &Path,
+    id: &str,
+    game_version: String,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<VerifyReport> {
+    let contents = fetch_modpack(id, &game_version, config).await?.contents;
+    let selected = select_contents(contents, config, options)?;
+    verify_files(output_dir, &selected.to_download, &selected.overrides)
+}
+
+/// Re-downloads the missing or corrupt files of an installed modpack and
+/// re-applies its missing overrides, leaving everything else untouched
+pub async fn repair(
+    output_dir: &Path,
+    id: &str,
+    game_version: String,
+    config: &Config,
+    options: &InstallOptions,
+) -> Result<VerifyReport> {
+    let contents = fetch_modpack(id, &game_version, config).await?.contents;
+    let selected = select_contents(contents, config, options)?;
+    let report =
+        verify_files(output_dir, &selected.to_download, &selected.overrides)?;
+
+    if report.is_ok() {
+        return Ok(report);
+    }
+
+    for path in &report.mismatched {
+        remove_file(output_dir.join(path)).await?;
+    }
+
+    let to_download = selected
+        .to_download
+        .into_iter()
+        .filter(|f| {
+            let path = &f.downloadable.output;
+            report.missing.contains(path) || report.mismatched.contains(path)
+        })
+        .collect();
+
+    let mut to_install = Vec::new();
+    for (path, source) in override_files(&selected.overrides)? {
+        if !report.missing_overrides.contains(&path) {
+            continue;
+        }
+        if let Some(parent) = output_dir.join(&path).parent() {
+            create_dir_all(parent).await?;
+        }
+        to_install.push((path.into_os_string(), source));
+    }
+
+    download(
+        output_dir.into(),
+        to_download,
+        to_install,
+        config,
+        &options.cancel,
+    )
+    .await?;
+    Ok(report)
+}
+
+/// Checks that every file in `to_download` is installed and intact, along
+/// with every override file. Files without a known hash, like CurseForge
+/// ones, only have their size checked, if it is known
+fn verify_files(
+    output_dir: &Path,
+    to_download: &[PackFile],
+    overrides: &[(OsString, PathBuf)],
+) -> Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+
+    for file in to_download {
+        let output = &file.downloadable.output;
+        let path = output_dir.join(output);
+        if !path.is_file() {
+            report.missing.push(output.clone());
+            continue;
+        }
+        let intact = match &file.sha512 {
+            Some(expected) => {
+                sha512_file(&path)? == expected.to_ascii_lowercase()
+            }
+            None if file.size > 0 => path.metadata()?.len() == file.size as u64,
+            None => true,
+        };
+        if !intact {
+            report.mismatched.push(output.clone());
+        }
+    }
+
+    report.missing_overrides = override_files(overrides)?
+        .into_iter()
+        .map(|(path, _)| path)
+        .filter(|path| !output_dir.join(path).exists())
+        .collect();
+
+    Ok(report)
+}
+
+/// Every file the overrides install, as (where it is installed relative to
+/// the instance, where it is installed from). When several overrides install
+/// the same file, the last one wins, like when they are installed
+fn override_files(
+    overrides: &[(OsString, PathBuf)],
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (name, source) in overrides {
+        let mut found = Vec::new();
+        if source.is_dir() {
+            let mut relative = Vec::new();
+            list_files(source, Path::new(""), &mut relative)?;
+            for path in relative {
+                found.push((Path::new(name).join(&path), source.join(path)));
+            }
+        } else {
+            found.push((PathBuf::from(name), source.clone()));
+        }
+
+        for (path, source) in found {
+            match files.iter_mut().find(|(p, _)| *p == path) {
+                Some(file) => file.1 = source,
+                None => files.push((path, source)),
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Lists every file under `directory`, relative to the directory `prefix`
+/// points to
+fn list_files(
+    directory: &Path,
+    prefix: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for entry in read_dir(directory)? {
+        let entry = entry?;
+        let path = prefix.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn sha512_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha512::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn read_overrides(directory: &Path) -> Result<Vec<(OsString, PathBuf)>> {
+    let mut to_install = Vec::new();
+    for file in read_dir(directory)? {
+        let file = file?;
+        to_install.push((file.file_name(), file.path()));
+    }
+    Ok(to_install)
+}
+
+/// Finds the files in `directory` that aren't part of the modpack anymore.
+/// Files that are already there are removed from `to_download` and
+/// `to_install`, since they don't need to be downloaded or installed again
+fn find_stale(
+    directory: &Path,
+    to_download: &mut Vec<PackFile>,
+    to_install: &mut Vec<(OsString, PathBuf)>,
+) -> Result<Vec<PathBuf>> {
+    let mut stale = Vec::new();
+    if !directory.is_dir() {
+        return Ok(stale);
+    }
+
     for file in read_dir(directory)? {
         let file = file?;
         // If it's a file
@@ -194,47 +1429,307 @@ async fn clean(
             {
                 // Don't install it
                 to_install.swap_remove(index);
-            // Or else, move the file to `directory`/.old
-            // If the file is a `.part` file or if the move failed, delete the file
-            } else if filename.ends_with("part")
-                || move_file(
-                    file.path(),
-                    directory.join(".old").join(filename),
-                    &FileCopyOptions::new(),
-                )
-                .is_err()
-            {
-                remove_file(file.path()).await?;
+            // Or else, it has to go
+            } else {
+                stale.push(file.path());
+            }
+        }
+    }
+    Ok(stale)
+}
+
+/// Copies the files in `output_dir` that installing `overrides` would replace
+/// to the `generation` directory in `output_dir/.old`, and lists the ones it
+/// would add. Files the overrides don't change aren't backed up. Returns
+/// whether anything was written
+fn backup_overrides(
+    output_dir: &Path,
+    overrides: &[(OsString, PathBuf)],
+    generation: &str,
+) -> Result<bool> {
+    let mut replaced = Vec::new();
+    let mut added = Vec::new();
+    for (file, source) in override_files(overrides)? {
+        let target = output_dir.join(&file);
+        if !target.is_file() {
+            added.push(file);
+        } else if !same_contents(&target, &source)? {
+            replaced.push(file);
+        }
+    }
+    if replaced.is_empty() && added.is_empty() {
+        return Ok(false);
+    }
+
+    let generation_dir = output_dir.join(".old").join(generation);
+    for file in &replaced {
+        let backup = generation_dir.join(file);
+        if let Some(parent) = backup.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(output_dir.join(file), backup)?;
+        debug!("Backed up {}", file.display());
+    }
+    if !added.is_empty() {
+        std::fs::create_dir_all(&generation_dir)?;
+        let added = added.iter().map(|f| f.to_string_lossy()).join("\n");
+        std::fs::write(generation_dir.join(ADDED_FILES_LIST), added)?;
+    }
+
+    Ok(true)
+}
+
+/// Whether both files have the same contents
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    if std::fs::metadata(a)?.len() != std::fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(std::fs::read(a)? == std::fs::read(b)?)
+}
+
+/// Lists the `.old` directories `clean` and `backup_overrides` put files in
+/// in the instance
+pub fn old_dirs(output_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    if !output_dir.is_dir() {
+        return Ok(dirs);
+    }
+
+    let overrides_old_dir = output_dir.join(".old");
+    if overrides_old_dir.is_dir() {
+        dirs.push(overrides_old_dir);
+    }
+    for entry in read_dir(output_dir)? {
+        let old_dir = entry?.path().join(".old");
+        if old_dir.is_dir() {
+            dirs.push(old_dir);
+        }
+    }
+    Ok(dirs)
+}
+
+/// What `rollback` put back in place
+#[derive(Debug, Clone, Default)]
+pub struct RollbackReport {
+    /// The generation that was restored
+    pub generation: Option<String>,
+    /// Files that were moved back from `.old`
+    pub restored: Vec<PathBuf>,
+    /// Files the rolled back update had downloaded, which were deleted
+    pub removed: Vec<PathBuf>,
+}
+
+/// Undoes the last update of `output_dir`, by deleting the files it
+/// downloaded and moving the ones it replaced back from the most recent
+/// `.old` generation, in every directory it touched: the mods, the resource
+/// packs and the overrides. Installing the modpack again updates them again
+pub fn rollback(output_dir: &Path) -> Result<RollbackReport> {
+    let mut report = RollbackReport::default();
+    let old_dirs = old_dirs(output_dir)?;
+
+    // generations are named after when they were made, so they sort by age
+    let mut generations = Vec::new();
+    for old_dir in &old_dirs {
+        for entry in read_dir(old_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                generations
+                    .push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+    }
+    let Some(generation) = generations.into_iter().max() else {
+        return Ok(report);
+    };
+
+    for directory in old_dirs.iter().filter_map(|dir| dir.parent()) {
+        restore_generation(directory, &generation, &mut report)?;
+    }
+    report.generation = Some(generation);
+    Ok(report)
+}
+
+/// Deletes the files the update that made `generation` added to
+/// `directory`, and moves the ones it replaced back from `.old`
+fn restore_generation(
+    directory: &Path,
+    generation: &str,
+    report: &mut RollbackReport,
+) -> Result<()> {
+    let generation_dir = directory.join(".old").join(generation);
+    if !generation_dir.is_dir() {
+        return Ok(());
+    }
+
+    let added_list = generation_dir.join(ADDED_FILES_LIST);
+    if added_list.is_file() {
+        for filename in std::fs::read_to_string(&added_list)?.lines() {
+            let path = directory.join(filename);
+            if path.is_file() {
+                std::fs::remove_file(&path)?;
+                debug!("Deleted {filename}");
+                report.removed.push(path);
             }
         }
+        std::fs::remove_file(&added_list)?;
+    }
+
+    // backed up overrides can be nested, e.g. in `config/`
+    let mut files = Vec::new();
+    list_files(&generation_dir, Path::new(""), &mut files)?;
+    for file in files {
+        let target = directory.join(&file);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // the file may have been put back by hand since, the old one wins
+        let options = FileCopyOptions {
+            overwrite: true,
+            ..FileCopyOptions::new()
+        };
+        move_file(generation_dir.join(file), &target, &options)?;
+        report.restored.push(target);
     }
+    std::fs::remove_dir_all(&generation_dir)?;
+
     Ok(())
 }
 
+/// Moves the stale files to the `generation` directory in the `.old`
+/// directory next to them. If the file is a `.part` file or if the move
+/// failed, the file is deleted
+async fn clean(stale: Vec<PathBuf>, generation: &str) -> Result<CleanedFiles> {
+    let mut cleaned = CleanedFiles::default();
+    for path in stale {
+        let (Some(directory), Some(filename)) =
+            (path.parent(), path.file_name())
+        else {
+            continue;
+        };
+        let filename = filename.to_string_lossy();
+        let generation_dir = directory.join(".old").join(generation);
+        create_dir_all(&generation_dir).await?;
+        let old_path = generation_dir.join(filename.as_ref());
+
+        if !filename.ends_with("part")
+            && move_file(&path, &old_path, &FileCopyOptions::new()).is_ok()
+        {
+            info!("Moved {filename} to .old");
+            cleaned.moved.push(old_path);
+        } else {
+            remove_file(&path).await?;
+            debug!("Deleted {filename}");
+            cleaned.deleted.push(path.clone());
+        }
+    }
+    Ok(cleaned)
+}
+
+/// Deletes everything but the `keep` most recently modified generations in
+/// `old_dir`, and returns how many files were deleted
+fn prune_old(old_dir: &Path, keep: usize) -> Result<usize> {
+    let mut entries = Vec::new();
+    for entry in read_dir(old_dir)? {
+        let entry = entry?;
+        let modified = entry.metadata()?.modified()?;
+        entries.push((modified, entry.path()));
+    }
+    entries.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut pruned = 0;
+    for (_, path) in entries.into_iter().skip(keep) {
+        if path.is_dir() {
+            let mut files = Vec::new();
+            list_files(&path, Path::new(""), &mut files)?;
+            pruned += files.len();
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+            pruned += 1;
+        }
+        debug!("Pruned {}", path.display());
+    }
+    Ok(pruned)
+}
+
+/// Downloads every file in `to_download` and installs the overrides in
+/// `to_install`. Cancelling `cancel` stops the downloads right away, leaving
+/// the overrides uninstalled
 async fn download(
     output_dir: PathBuf,
-    to_download: Vec<Downloadable>,
+    mut to_download: Vec<PackFile>,
     to_install: Vec<(OsString, PathBuf)>,
+    config: &Config,
+    cancel: &CancellationToken,
 ) -> Result<()> {
     create_dir_all(&*output_dir).await?;
+    // start with the biggest files, so they don't end up finishing last
+    to_download.sort_unstable_by_key(|f| Reverse(f.size));
     let mut tasks = JoinSet::new();
-    let semaphore = Arc::new(Semaphore::new(75));
+    let semaphore =
+        Arc::new(Semaphore::new(config.network.download_concurrency()));
+    let retry = config.network.retry_policy();
     let output_dir = Arc::new(output_dir);
-    for downloadable in to_download {
-        let permit = semaphore.clone().acquire_owned().await?;
+
+    let files_bar = PROGRESS.add(
+        ProgressBar::new(to_download.len() as u64)
+            .with_style(STYLE_FILES.clone()),
+    );
+    if let Some(tick) = config.progress_tick() {
+        files_bar.enable_steady_tick(tick);
+    }
+
+    for mut file in to_download {
+        let mirror = config.network.modrinth_mirror.as_deref();
+        apply_mirror(&mut file.downloadable.download_url, mirror)?;
+        for url in &mut file.alternates {
+            apply_mirror(url, mirror)?;
+        }
+        let permit = tokio::select! {
+            permit = semaphore.clone().acquire_owned() => permit?,
+            _ = cancel.cancelled() => break,
+        };
         let output_dir = output_dir.clone();
+        let cancel = cancel.clone();
+        let file_bar = PROGRESS.insert_before(
+            &files_bar,
+            byte_progress_bar(file.size as u64, config),
+        );
         tasks.spawn(async move {
             let _permit = permit;
-            info!("Downloading {}", downloadable.filename());
-            downloadable.download(&CLIENT, &output_dir, |_| {}).await?;
-            Ok::<(), UklientError>(())
+            debug!("Downloading {}", file.filename());
+            let result = tokio::select! {
+                result = file.download(&output_dir, &file_bar, retry) => result,
+                _ = cancel.cancelled() => Err(Cancelled),
+            };
+            file_bar.finish_and_clear();
+            result
         });
     }
+    // returning early drops `tasks`, which aborts the downloads left
     while let Some(res) = tasks.join_next().await {
-        res??;
+        if let Err(e) = res? {
+            files_bar.finish_and_clear();
+            return Err(e);
+        }
+        files_bar.inc(1);
+    }
+    files_bar.finish_and_clear();
+    if cancel.is_cancelled() {
+        info!("Cancelled the modpack download");
+        return Err(Cancelled);
     }
     for installable in to_install {
-        if installable.1.is_file() {
+        if config.link_overrides {
+            let target = output_dir.join(&installable.0);
+            if installable.1.is_file() {
+                link_file(&installable.1, &target)?;
+            } else if installable.1.is_dir() {
+                link_dir(&installable.1, &target)?;
+            } else {
+                return Err(UnknownTypeError(installable.0));
+            }
+        } else if installable.1.is_file() {
             copy(installable.1, output_dir.join(&installable.0)).await?;
         } else if installable.1.is_dir() {
             let mut copy_options = DirCopyOptions::new();
@@ -249,6 +1744,72 @@ async fn download(
     Ok(())
 }
 
+/// Points `url` to the configured mirror if it is hosted on Modrinth's CDN.
+/// The mirror is a host with an optional port (`mirror.local:8080`), or a
+/// URL whose scheme is used too
+fn apply_mirror(url: &mut Url, mirror: Option<&str>) -> Result<()> {
+    let Some(mirror) = mirror else {
+        return Ok(());
+    };
+    if url.host_str() != Some(MODRINTH_CDN) {
+        return Ok(());
+    }
+
+    let mirror = if mirror.contains("://") {
+        Url::parse(mirror)?
+    } else {
+        Url::parse(&format!("{}://{mirror}", url.scheme()))?
+    };
+    // only fails when going between special and non-special schemes
+    if url.set_scheme(mirror.scheme()).is_err() {
+        return Err(MetaError("modrinth mirror scheme"));
+    }
+    url.set_host(mirror.host_str())?;
+    if url.set_port(mirror.port()).is_err() {
+        return Err(MetaError("modrinth mirror port"));
+    }
+
+    Ok(())
+}
+
+/// Hardlinks `from` to `to`, copying it instead if the link can't be made
+/// (e.g. when both paths are on different filesystems). A directory in the
+/// way is an error, it is never deleted
+fn link_file(from: &Path, to: &Path) -> Result<()> {
+    if to.is_dir() {
+        return Err(ModpackFileError(
+            to.into(),
+            "is a directory, but the modpack has a file there",
+        ));
+    }
+    if to.exists() {
+        std::fs::remove_file(to)?;
+    }
+
+    if let Err(e) = std::fs::hard_link(from, to) {
+        debug!("Could not link {}, copying it instead: {e}", from.display());
+        std::fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
+/// Recreates the `from` directory tree at `to`, linking every file in it
+fn link_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            link_dir(&entry.path(), &target)?;
+        } else {
+            link_file(&entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
 fn find_dupes_by_key<T, V, F>(slice: &mut [T], key: F) -> Vec<usize>
 where
     V: Eq + Ord,
@@ -267,3 +1828,368 @@ where
     indices.reverse();
     indices
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Deref;
+
+    /// A directory in the system's temporary directory, deleted when it is
+    /// dropped, even if the test panicked
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("uklient-test-{}", rand::random::<u64>()));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Deref for TestDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn pack_file(output: &str) -> PackFile {
+        let url = format!("https://{MODRINTH_CDN}/data/abcdef/{output}");
+        PackFile {
+            downloadable: Downloadable {
+                download_url: Url::parse(&url).unwrap(),
+                output: output.into(),
+                length: 1,
+            },
+            alternates: Vec::new(),
+            size: 1,
+            sha512: None,
+            best_effort: false,
+        }
+    }
+
+    #[test]
+    fn dupes_across_directories_are_removed_once() {
+        let mut to_download = vec![
+            pack_file("mods/sodium.jar"),
+            pack_file("resourcepacks/sodium.jar"),
+            pack_file("mods/lithium.jar"),
+            pack_file("resourcepacks/faithful.zip"),
+            pack_file("mods/sodium.jar"),
+        ];
+        remove_dupes(&mut to_download);
+
+        let filenames = to_download
+            .iter()
+            .map(PackFile::filename)
+            .sorted()
+            .collect_vec();
+        assert_eq!(filenames, ["faithful.zip", "lithium.jar", "sodium.jar"]);
+    }
+
+    /// Prepares installing a pack without any files, whose overrides are
+    /// the files in `overrides`, over an instance with a mod installed
+    fn prepare_overrides_only(
+        overrides: &[&str],
+    ) -> (PreparedInstall, PathBuf) {
+        let root = TestDir::new();
+        let output_dir = root.join("instance");
+        let tmp_dir = root.join("pack");
+        std::fs::create_dir_all(output_dir.join("mods")).unwrap();
+        std::fs::create_dir_all(tmp_dir.join("overrides")).unwrap();
+        std::fs::write(output_dir.join("mods").join("sodium.jar"), "").unwrap();
+        for file in overrides {
+            let path = tmp_dir.join("overrides").join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let contents = ModpackContents {
+            files: Vec::new(),
+            curseforge_files: Vec::new(),
+            overrides: read_overrides(&tmp_dir.join("overrides")).unwrap(),
+            client_overrides: Vec::new(),
+            server_overrides: Vec::new(),
+        };
+        let prepared = prepare_contents(
+            &output_dir,
+            contents,
+            "1.0.0".into(),
+            None,
+            &Config::default(),
+            &InstallOptions::default(),
+        );
+        (prepared.unwrap(), output_dir)
+    }
+
+    #[test]
+    fn overrides_only_packs_leave_mods_alone() {
+        let (prepared, output_dir) =
+            prepare_overrides_only(&["shaderpacks/BSL.zip"]);
+        assert!(!prepared.pack_is_empty);
+        assert!(prepared.to_download.is_empty());
+        assert!(prepared.stale.is_empty());
+        assert!(!prepared.managed_dirs.contains(&output_dir.join("mods")));
+        let overrides = prepared.overrides.iter().map(|(name, _)| name);
+        assert_eq!(overrides.collect_vec(), ["shaderpacks"]);
+    }
+
+    #[test]
+    fn packs_without_files_or_overrides_are_empty() {
+        let (prepared, _) = prepare_overrides_only(&[]);
+        assert!(prepared.pack_is_empty);
+        assert!(prepared.stale.is_empty());
+    }
+
+    #[tokio::test]
+    async fn vanilla_packs_have_no_loader_version() {
+        for loader in ["minecraft", "Vanilla"] {
+            let info = LoaderInfo::from(loader, &"1.20.1".into(), None).await;
+            let info = info.unwrap();
+            assert!(matches!(info.loader, ModLoader::Vanilla));
+            assert!(info.version.is_none());
+        }
+    }
+
+    #[test]
+    fn link_file_refuses_to_replace_a_directory() {
+        let root = TestDir::new();
+        std::fs::create_dir_all(root.join("options.txt")).unwrap();
+        std::fs::write(root.join("override.txt"), "").unwrap();
+
+        let result =
+            link_file(&root.join("override.txt"), &root.join("options.txt"));
+        assert!(matches!(result, Err(ModpackFileError(..))));
+        assert!(root.join("options.txt").is_dir());
+    }
+
+    #[test]
+    fn mirrors_keep_their_port() {
+        let cdn = format!("https://{MODRINTH_CDN}/data/abcdef/sodium.jar");
+        for (mirror, expected) in [
+            (
+                "mirror.local",
+                "https://mirror.local/data/abcdef/sodium.jar",
+            ),
+            (
+                "mirror.local:8080",
+                "https://mirror.local:8080/data/abcdef/sodium.jar",
+            ),
+            (
+                "http://10.0.0.2:8080",
+                "http://10.0.0.2:8080/data/abcdef/sodium.jar",
+            ),
+        ] {
+            let mut url = Url::parse(&cdn).unwrap();
+            apply_mirror(&mut url, Some(mirror)).unwrap();
+            assert_eq!(url.as_str(), expected);
+        }
+    }
+
+    #[test]
+    fn mirrors_only_replace_modrinth() {
+        let other = "https://edge.forgecdn.net/files/sodium.jar";
+        let mut url = Url::parse(other).unwrap();
+        apply_mirror(&mut url, Some("mirror.local:8080")).unwrap();
+        assert_eq!(url.as_str(), other);
+    }
+
+    /// An index entry for `path`, as it is in `modrinth.index.json`
+    fn index_file(path: &str, client: &str) -> ModpackFile {
+        serde_json::from_value(serde_json::json!({
+            "path": path,
+            "hashes": {
+                "sha1": "0".repeat(40),
+                "sha512": "0".repeat(128),
+            },
+            "env": { "client": client, "server": "required" },
+            "downloads": [format!("https://{MODRINTH_CDN}/data/abcdef/{path}")],
+            "fileSize": 1,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn verify_skips_files_the_side_does_not_support() {
+        let root = TestDir::new();
+        let files = vec![
+            index_file("mods/sodium.jar", "required"),
+            index_file("mods/spark-server.jar", "unsupported"),
+            index_file("mods/zoomify.jar", "optional"),
+        ];
+        let (files, _) =
+            select_files(files, Vec::new(), &InstallOptions::default())
+                .unwrap();
+        let to_download = files.into_iter().map(PackFile::from).collect_vec();
+        let report = verify_files(&root, &to_download, &[]);
+        assert_eq!(report.unwrap().missing, [Path::new("mods/sodium.jar")]);
+    }
+
+    #[test]
+    fn extra_mods_are_kept_without_a_prompt() {
+        let files = vec![
+            index_file("mods/sodium.jar", "required"),
+            index_file("mods/zoomify.jar", "optional"),
+        ];
+        let extra = ExtraMod {
+            url: "https://example.com/extra.jar".into(),
+            sha512: None,
+            filename: None,
+        };
+        let extra_mods = vec![PackFile::extra(&extra).unwrap()];
+
+        let (files, extra_mods) =
+            select_files(files, extra_mods, &InstallOptions::default())
+                .unwrap();
+        let paths = files.iter().map(|f| f.path.as_path()).collect_vec();
+        assert_eq!(paths, [Path::new("mods/sodium.jar")]);
+        assert_eq!(extra_mods.len(), 1);
+    }
+
+    #[test]
+    fn verify_follows_the_install_options() {
+        let root = TestDir::new();
+        let output_dir = root.join("instance");
+        let overrides_dir = root.join("pack").join("overrides");
+        std::fs::create_dir_all(output_dir.join("mods")).unwrap();
+        std::fs::create_dir_all(overrides_dir.join("resourcepacks")).unwrap();
+        std::fs::write(overrides_dir.join("resourcepacks/pack.zip"), "")
+            .unwrap();
+        let files = vec![
+            index_file("mods/sodium.jar", "required"),
+            index_file("resourcepacks/faithful.zip", "required"),
+        ];
+        let contents = ModpackContents {
+            files,
+            curseforge_files: Vec::new(),
+            overrides: read_overrides(&overrides_dir).unwrap(),
+            client_overrides: Vec::new(),
+            server_overrides: Vec::new(),
+        };
+        let config = Config {
+            // the project ID in the URLs of `index_file`
+            low_end_excluded: vec!["abcdef".into()],
+            extra_mods: vec![ExtraMod {
+                url: "https://example.com/extra.jar".into(),
+                sha512: None,
+                filename: None,
+            }],
+            ..Config::default()
+        };
+        let options = InstallOptions {
+            skip_resourcepacks: true,
+            low_end: true,
+            ..InstallOptions::default()
+        };
+
+        let selected = select_contents(contents, &config, &options).unwrap();
+        let report = verify_files(
+            &output_dir,
+            &selected.to_download,
+            &selected.overrides,
+        )
+        .unwrap();
+        assert_eq!(report.missing, [Path::new("mods/extra.jar")]);
+        assert!(report.missing_overrides.is_empty());
+    }
+
+    #[test]
+    fn archive_overrides_are_listed_by_top_level_name() {
+        let names = [
+            "modrinth.index.json",
+            "overrides/",
+            "overrides/options.txt",
+            "overrides/config/sodium.json",
+            "overrides/config/iris.json",
+            "overridesx/ignored.txt",
+            "client-overrides/shaderpacks/BSL.zip",
+        ];
+
+        let overrides = archive_overrides(names.into_iter(), "overrides");
+        assert_eq!(
+            overrides,
+            [
+                (
+                    OsString::from("options.txt"),
+                    PathBuf::from("overrides/options.txt")
+                ),
+                (OsString::from("config"), PathBuf::from("overrides/config")),
+            ]
+        );
+    }
+
+    #[test]
+    fn tmp_dir_name_is_a_single_component() {
+        assert_eq!(
+            tmp_dir_name("Fabulously Optimized"),
+            "Fabulously Optimized"
+        );
+        assert_eq!(tmp_dir_name(".."), "modpack");
+        assert_eq!(tmp_dir_name("../../etc"), "_.._etc");
+        assert_eq!(tmp_dir_name("/home/user"), "_home_user");
+        assert_eq!(tmp_dir_name("C:\\Windows"), "C__Windows");
+        assert_eq!(tmp_dir_name(""), "modpack");
+    }
+
+    #[test]
+    fn restore_generation_overwrites_existing_files() {
+        let root = TestDir::new();
+        let directory = root.join("mods");
+        let generation_dir = directory.join(".old").join("20240101-000000");
+        std::fs::create_dir_all(&generation_dir).unwrap();
+        std::fs::write(directory.join("sodium.jar"), "new").unwrap();
+        std::fs::write(directory.join("lithium.jar"), "added").unwrap();
+        std::fs::write(generation_dir.join("sodium.jar"), "old").unwrap();
+        std::fs::write(generation_dir.join(ADDED_FILES_LIST), "lithium.jar")
+            .unwrap();
+
+        let mut report = RollbackReport::default();
+        restore_generation(&directory, "20240101-000000", &mut report).unwrap();
+        let sodium = std::fs::read_to_string(directory.join("sodium.jar"));
+        assert_eq!(sodium.unwrap(), "old");
+        assert!(!directory.join("lithium.jar").exists());
+        assert!(!generation_dir.exists());
+        assert_eq!(report.restored, [directory.join("sodium.jar")]);
+    }
+
+    #[test]
+    fn rollback_restores_overrides() {
+        let root = TestDir::new();
+        let output_dir = root.join("instance");
+        let overrides_dir = root.join("overrides");
+        std::fs::create_dir_all(output_dir.join("config")).unwrap();
+        std::fs::create_dir_all(overrides_dir.join("config")).unwrap();
+        std::fs::write(output_dir.join("config/sodium.json"), "edited")
+            .unwrap();
+        std::fs::write(output_dir.join("options.txt"), "same").unwrap();
+        std::fs::write(overrides_dir.join("config/sodium.json"), "pack")
+            .unwrap();
+        std::fs::write(overrides_dir.join("config/iris.json"), "pack").unwrap();
+        std::fs::write(overrides_dir.join("options.txt"), "same").unwrap();
+        let overrides = read_overrides(&overrides_dir).unwrap();
+
+        assert!(backup_overrides(&output_dir, &overrides, "20240101-000000")
+            .unwrap());
+        // what installing the overrides does
+        std::fs::write(output_dir.join("config/sodium.json"), "pack").unwrap();
+        std::fs::write(output_dir.join("config/iris.json"), "pack").unwrap();
+
+        let report = rollback(&output_dir).unwrap();
+        assert_eq!(report.generation.as_deref(), Some("20240101-000000"));
+        assert_eq!(report.restored, [output_dir.join("config/sodium.json")]);
+        let sodium =
+            std::fs::read_to_string(output_dir.join("config/sodium.json"));
+        assert_eq!(sodium.unwrap(), "edited");
+        assert!(!output_dir.join("config/iris.json").exists());
+        let options = std::fs::read_to_string(output_dir.join("options.txt"));
+        assert_eq!(options.unwrap(), "same");
+    }
+}
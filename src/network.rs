@@ -0,0 +1,199 @@
+use crate::{Result, UklientError};
+use once_cell::sync::OnceCell;
+use rand::Rng;
+use reqwest::{Client, Proxy};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+const DEFAULT_DOWNLOAD_RETRIES: usize = 3;
+const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+const DEFAULT_RETRY_JITTER: f64 = 0.2;
+
+/// The settings `CLIENT` is built with, set once the config is read
+static SETTINGS: OnceCell<NetworkConfig> = OnceCell::new();
+
+/// The `[network]` section of the config. It applies to uklient's own
+/// requests: theseus and daedalus, which download the game, the loaders and
+/// the assets, build their own clients and only get the proxy
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// A host to download files from instead of Modrinth's CDN, with an
+    /// optional port (`mirror.local:8080`) or scheme (`http://mirror.local`)
+    pub modrinth_mirror: Option<String>,
+    /// A proxy every request goes through (`http://localhost:3128`), instead
+    /// of the one set in the `HTTP_PROXY` and `HTTPS_PROXY` variables
+    pub proxy: Option<String>,
+    /// How many seconds to wait for a server to accept a connection, unset
+    /// to wait for as long as the system allows. Game downloads aren't
+    /// affected
+    pub connect_timeout_secs: Option<u64>,
+    /// How many modpack files are downloaded at once. Defaults to 4 per CPU
+    pub download_concurrency: Option<usize>,
+    /// How many times a modpack file is downloaded again when it fails.
+    /// Defaults to 3
+    pub download_retries: Option<usize>,
+    /// How long to wait before downloading a file again, doubled after every
+    /// retry. Defaults to 500
+    pub retry_delay_ms: Option<u64>,
+    /// How much retry delays are randomly shortened or lengthened, from 0 to
+    /// 1, so that files failing together aren't all retried at once.
+    /// Defaults to 0.2
+    pub retry_jitter: Option<f64>,
+}
+
+impl NetworkConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(jitter) = self.retry_jitter {
+            if !(0.0..=1.0).contains(&jitter) {
+                return Err(UklientError::ConfigError(
+                    "retry_jitter must be between 0 and 1".into(),
+                ));
+            }
+        }
+
+        if let Some(proxy) = &self.proxy {
+            if let Err(e) = Proxy::all(proxy) {
+                return Err(UklientError::ConfigError(format!(
+                    "invalid proxy {proxy}: {e}"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How many modpack files can be downloaded at once, at least 1
+    pub fn download_concurrency(&self) -> usize {
+        self.download_concurrency
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |n| n.get()) * 4
+            })
+            .max(1)
+    }
+
+    /// How failed downloads are retried
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.download_retries.unwrap_or(DEFAULT_DOWNLOAD_RETRIES),
+            delay: Duration::from_millis(
+                self.retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS),
+            ),
+            jitter: self.retry_jitter.unwrap_or(DEFAULT_RETRY_JITTER),
+        }
+    }
+}
+
+/// How many times failed downloads are retried, and how long to wait between
+/// retries
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: usize,
+    delay: Duration,
+    jitter: f64,
+}
+
+impl RetryPolicy {
+    /// How long to wait before the `retry`th retry, counting from 0: the
+    /// delay doubles every time, and is randomly shortened or lengthened by
+    /// up to `jitter` so that concurrent downloads don't retry in sync
+    pub fn delay(&self, retry: usize) -> Duration {
+        let factor =
+            1.0 + self.jitter * rand::thread_rng().gen_range(-1.0..=1.0);
+        (self.delay * (1 << retry.min(10))).mul_f64(factor)
+    }
+}
+
+/// Sets the settings `CLIENT` is built with. It must be called before
+/// `CLIENT` is first used, later calls are ignored. The proxy is also set in
+/// the environment, since that is the only way to pass it to the clients of
+/// theseus and daedalus
+pub fn init(config: &NetworkConfig) {
+    if SETTINGS.set(config.clone()).is_err() {
+        warn!("The network settings were already set");
+        return;
+    }
+
+    if let Some(proxy) = &config.proxy {
+        for var in ["HTTP_PROXY", "HTTPS_PROXY"] {
+            std::env::set_var(var, proxy);
+        }
+    }
+}
+
+/// The settings given to `init`, or the defaults if it wasn't called
+pub fn settings() -> NetworkConfig {
+    SETTINGS.get().cloned().unwrap_or_default()
+}
+
+/// An HTTP client using the proxy and timeouts of `config`. Settings that
+/// can't be applied are skipped with a warning
+pub fn build_client(config: &NetworkConfig) -> Client {
+    let mut builder = Client::builder();
+    if let Some(proxy) = &config.proxy {
+        match Proxy::all(proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => warn!("Ignoring the invalid proxy {proxy}: {e}"),
+        }
+    }
+    if let Some(timeout) = config.connect_timeout_secs {
+        builder = builder.connect_timeout(Duration::from_secs(timeout));
+    }
+
+    builder.build().unwrap_or_else(|e| {
+        warn!("Could not configure the HTTP client, using the default: {e}");
+        Client::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64) -> RetryPolicy {
+        NetworkConfig {
+            retry_delay_ms: Some(100),
+            retry_jitter: Some(jitter),
+            ..NetworkConfig::default()
+        }
+        .retry_policy()
+    }
+
+    #[test]
+    fn retry_delay_doubles() {
+        let policy = policy(0.0);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        // the doubling stops at some point, so that it can't overflow
+        assert_eq!(policy.delay(64), policy.delay(10));
+    }
+
+    #[test]
+    fn retry_delay_jitter_stays_in_bounds() {
+        let policy = policy(0.2);
+        for retry in 0..5 {
+            let base = 100.0 * 2f64.powi(retry as i32);
+            for _ in 0..100 {
+                let delay = policy.delay(retry).as_secs_f64() * 1000.0;
+                assert!(
+                    (base * 0.8 - 0.001..=base * 1.2 + 0.001).contains(&delay)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn retry_jitter_is_validated() {
+        for jitter in [-0.1, 1.5] {
+            let config = NetworkConfig {
+                retry_jitter: Some(jitter),
+                ..NetworkConfig::default()
+            };
+            assert!(config.validate().is_err());
+        }
+        assert!(NetworkConfig::default().validate().is_ok());
+    }
+}
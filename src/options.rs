@@ -0,0 +1,64 @@
+use crate::Result;
+use std::path::Path;
+
+const OPTIONS_FILE: &str = "options.txt";
+
+/// The value of `key` in the instance's `options.txt`, if it is set
+pub fn get_option(base_path: &Path, key: &str) -> Result<Option<String>> {
+    Ok(read_options(base_path)?.lines().find_map(|line| {
+        line.split_once(':')
+            .filter(|(k, _)| *k == key)
+            .map(|(_, value)| value.to_owned())
+    }))
+}
+
+/// Sets `key` to `value` in the instance's `options.txt`, leaving every other
+/// option untouched. The file is created if the game never wrote it
+pub fn set_option(base_path: &Path, key: &str, value: &str) -> Result<()> {
+    let content = read_options(base_path)?;
+
+    let entry = format!("{key}:{value}");
+    let mut found = false;
+    let mut lines = content
+        .lines()
+        .map(|line| match line.split_once(':') {
+            Some((k, _)) if k == key => {
+                found = true;
+                entry.clone()
+            }
+            _ => line.to_owned(),
+        })
+        .collect::<Vec<_>>();
+    if !found {
+        lines.push(entry);
+    }
+
+    write_options(base_path, &lines)
+}
+
+/// Removes `key` from the instance's `options.txt`, so that the game uses its
+/// default value
+pub fn unset_option(base_path: &Path, key: &str) -> Result<()> {
+    let content = read_options(base_path)?;
+    let lines = content
+        .lines()
+        .filter(|line| line.split_once(':').map_or(true, |(k, _)| k != key))
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+
+    write_options(base_path, &lines)
+}
+
+fn read_options(base_path: &Path) -> Result<String> {
+    let path = base_path.join(OPTIONS_FILE);
+    if path.try_exists()? {
+        Ok(std::fs::read_to_string(&path)?)
+    } else {
+        Ok(String::new())
+    }
+}
+
+fn write_options(base_path: &Path, lines: &[String]) -> Result<()> {
+    std::fs::write(base_path.join(OPTIONS_FILE), lines.join("\n") + "\n")?;
+    Ok(())
+}
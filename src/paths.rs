@@ -0,0 +1,68 @@
+use libium::HOME;
+use std::path::PathBuf;
+
+/// Where the config file lives
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| HOME.join(".config"))
+        .join("uklient")
+}
+
+/// Where everything that can't be downloaded again lives
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| HOME.join(".local").join("share"))
+        .join("uklient")
+}
+
+/// Where everything that can be downloaded again lives
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| HOME.join(".cache"))
+        .join("uklient")
+}
+
+/// Where downloaded JDKs are extracted
+pub fn java_dir() -> PathBuf {
+    data_dir().join("java")
+}
+
+/// Where every modpack instance is installed
+pub fn instances_dir() -> PathBuf {
+    data_dir().join("instances")
+}
+
+/// Where the Java binaries found by previous launches are remembered
+pub fn java_cache_path() -> PathBuf {
+    config_dir().join("java.json")
+}
+
+/// Where the credentials of every logged in account are kept
+pub fn accounts_path() -> PathBuf {
+    data_dir().join("accounts.json")
+}
+
+/// Where older versions kept the credentials of the only account
+pub fn credentials_path() -> PathBuf {
+    data_dir().join("credentials.json")
+}
+
+/// Where the gpg keyring used to check JDK signatures lives
+pub fn gnupg_dir() -> PathBuf {
+    data_dir().join("gnupg")
+}
+
+/// Where downloaded modpacks are kept
+pub fn modpack_cache_dir() -> PathBuf {
+    cache_dir().join("modpacks")
+}
+
+/// Where loader metadata is cached
+pub fn meta_cache_dir() -> PathBuf {
+    cache_dir().join("meta")
+}
+
+/// Where archives are downloaded to and modpacks are extracted
+pub fn tmp_dir() -> PathBuf {
+    cache_dir().join("tmp")
+}
@@ -0,0 +1,149 @@
+use crate::config::Config;
+use crate::{paths, Result};
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::fs::{read_dir, remove_dir_all, remove_file};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{RwLock, RwLockReadGuard};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// How long running operations get to stop before their files are removed
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Every operation's token is a child of this one, so cancelling it cancels
+/// all of them
+static SHUTDOWN: Lazy<CancellationToken> = Lazy::new(CancellationToken::new);
+/// Read locked by every running operation, so that `stop_everything` can
+/// wait for them to stop
+static RUNNING: Lazy<RwLock<()>> = Lazy::new(|| RwLock::new(()));
+/// The partial downloads running operations are writing outside of
+/// uklient's own directories
+static IN_FLIGHT: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(Default::default);
+
+/// An install or download that can be cancelled, on its own with `token` or
+/// along with every other operation with `stop_everything`
+pub struct Operation {
+    pub token: CancellationToken,
+    _running: RwLockReadGuard<'static, ()>,
+}
+
+pub async fn start_operation() -> Operation {
+    Operation {
+        token: SHUTDOWN.child_token(),
+        _running: RUNNING.read().await,
+    }
+}
+
+/// A partial download that `stop_everything` removes, for as long as this
+/// is alive
+pub struct InFlight(PathBuf);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Marks `path` as being written by a running operation
+pub fn track(path: PathBuf) -> InFlight {
+    IN_FLIGHT.lock().unwrap().insert(path.clone());
+    InFlight(path)
+}
+
+/// What `stop_everything` cleaned up
+#[derive(Debug, Default)]
+pub struct StopReport {
+    /// Whether every operation stopped before the files were removed
+    pub stopped: bool,
+    pub removed: Vec<PathBuf>,
+}
+
+/// Cancels every running install and Java download, waits a bit for them to
+/// stop, then removes the partial downloads and extracted modpacks they left
+/// behind. Partial Java downloads can't be resumed afterwards. Only uklient's
+/// own tmp and cache directories are cleaned up, along with the files that
+/// were being downloaded into instances. This can be called at any point,
+/// even when nothing is running
+pub async fn stop_everything(config: &Config) -> StopReport {
+    SHUTDOWN.cancel();
+    // stopped operations stop tracking their files, so this is done first
+    let in_flight = IN_FLIGHT.lock().unwrap().clone();
+    let stopped = tokio::time::timeout(STOP_GRACE_PERIOD, RUNNING.write())
+        .await
+        .is_ok();
+
+    let mut report = StopReport {
+        stopped,
+        ..StopReport::default()
+    };
+    for tmp_dir in [paths::tmp_dir(), config.tmp_dir()] {
+        if let Err(e) = clean_tmp_dir(&tmp_dir, &mut report.removed) {
+            warn!("Could not clean up {}: {e}", tmp_dir.display());
+        }
+    }
+    let cache_dir = paths::modpack_cache_dir();
+    if let Err(e) = remove_part_files(&cache_dir, &mut report.removed) {
+        warn!("Could not clean up {}: {e}", cache_dir.display());
+    }
+    for path in in_flight {
+        match remove_file(&path) {
+            Ok(()) => {
+                debug!("Removed {}", path.display());
+                report.removed.push(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Could not remove {}: {e}", path.display()),
+        }
+    }
+
+    report
+}
+
+/// Removes the partial downloads and the extracted modpacks in `dir`, which
+/// only uklient writes to. The finished Java archives are kept
+fn clean_tmp_dir(dir: &Path, removed: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_dir_all(&path)?;
+        } else if is_part_file(&path) {
+            remove_file(&path)?;
+        } else {
+            continue;
+        }
+        debug!("Removed {}", path.display());
+        removed.push(path);
+    }
+    Ok(())
+}
+
+/// Removes every partial download in `dir` and its subdirectories
+fn remove_part_files(dir: &Path, removed: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            remove_part_files(&path, removed)?;
+        } else if is_part_file(&path) {
+            remove_file(&path)?;
+            debug!("Removed {}", path.display());
+            removed.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_part_file(path: &Path) -> bool {
+    path.extension().map_or(false, |e| e == "part")
+}
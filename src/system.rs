@@ -0,0 +1,60 @@
+use crate::config::{Config, MemoryCheck};
+use crate::{Result, UklientError};
+use std::path::Path;
+use sysinfo::{System, SystemExt};
+use tracing::warn;
+
+const MIN_RECOMMENDED_MEMORY: u64 = 2048;
+const MAX_RECOMMENDED_MEMORY: u64 = 8192;
+
+/// The physical memory of this machine, in megabytes
+pub fn total_memory() -> u64 {
+    let mut system = System::new();
+    system.refresh_memory();
+    system.total_memory() / (1024 * 1024)
+}
+
+/// How much memory the game should get on this machine, in megabytes: half
+/// of it, between 2048 and 8192. `None` if the machine's memory can't be read
+pub fn recommended_max_memory() -> Option<u32> {
+    let total = total_memory();
+    (total > 0).then(|| {
+        (total / 2).clamp(MIN_RECOMMENDED_MEMORY, MAX_RECOMMENDED_MEMORY) as u32
+    })
+}
+
+/// The space left on the disk `path` is on, in bytes, if it can be read
+pub fn available_space(path: &Path) -> Option<u64> {
+    fs2::available_space(path).ok()
+}
+
+/// Makes sure `max_memory` (in megabytes) leaves enough memory for the rest
+/// of the system, warning or failing depending on the config
+pub fn check_memory(max_memory: u32, config: &Config) -> Result<()> {
+    if config.memory_check == MemoryCheck::Off {
+        return Ok(());
+    }
+
+    let total = total_memory();
+    // the total can't be read on some systems
+    if total == 0 {
+        return Ok(());
+    }
+
+    let safe = (total as f64 * config.max_memory_fraction()) as u64;
+    if u64::from(max_memory) <= safe {
+        return Ok(());
+    }
+
+    match config.memory_check {
+        MemoryCheck::Error => Err(UklientError::TooMuchMemory {
+            max_memory,
+            total,
+            safe,
+        }),
+        _ => {
+            warn!("The game can use {max_memory} MB but this machine only has {total} MB, consider setting max_memory to {safe} or less");
+            Ok(())
+        }
+    }
+}
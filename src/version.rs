@@ -1,27 +1,67 @@
+use std::cmp::Ordering;
 use std::fmt::Display;
+use std::str::FromStr;
 
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MinecraftVersion {
-    pub minor: u8,
-    pub patch: u8,
+use crate::CLIENT;
+
+const VERSION_MANIFEST_URL: &str =
+    "https://launchermeta.mojang.com/mc/game/version_manifest_v2.json";
+
+pub const ONE_SEVENTEEN: MinecraftVersion = MinecraftVersion::new(17, 0);
+pub const ONE_TWENTY_POINT_FIVE: MinecraftVersion =
+    MinecraftVersion::new(20, 5);
+
+/// Releases are compared by their minor version, then by their patch, and
+/// snapshots by their year, week and revision. Snapshots are considered
+/// newer than every release
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MinecraftVersion {
+    Release { minor: u8, patch: u8 },
+    Snapshot { year: u8, week: u8, revision: char },
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum VersionError {
     #[error("Invalid {0} version")]
     InvalidVersion(&'static str),
-    #[error("Snapshots are unsupported")]
-    SnapshotsAreUnsupported,
 }
 
 impl MinecraftVersion {
+    pub const fn new(minor: u8, patch: u8) -> Self {
+        MinecraftVersion::Release { minor, patch }
+    }
+
+    /// The Java version Minecraft needs to run. Snapshots are newer than
+    /// every release, so they need the latest one
+    pub fn required_java_major(&self) -> u8 {
+        if *self >= ONE_TWENTY_POINT_FIVE {
+            21
+        } else if *self >= ONE_SEVENTEEN {
+            17
+        } else {
+            8
+        }
+    }
+
     pub fn parse(source: &str) -> Result<Self, VersionError> {
-        let snapshot_regex = Regex::new(r"\d+w\d{2}[a-z]").unwrap();
-        if snapshot_regex.find(source).is_some() {
-            return Err(VersionError::SnapshotsAreUnsupported);
+        let snapshot_regex = Regex::new(r"^(\d+)w(\d{2})([a-z])$").unwrap();
+        if let Some(captures) = snapshot_regex.captures(source) {
+            let year = captures[1]
+                .parse::<u8>()
+                .map_err(|_| VersionError::InvalidVersion("snapshot year"))?;
+            let week = captures[2]
+                .parse::<u8>()
+                .map_err(|_| VersionError::InvalidVersion("snapshot week"))?;
+            let revision = captures[3].chars().next().unwrap_or('a');
+
+            return Ok(MinecraftVersion::Snapshot {
+                year,
+                week,
+                revision,
+            });
         }
 
         let mut parts = source.split('.');
@@ -46,32 +86,282 @@ impl MinecraftVersion {
             None => 0, // no patch version specified, so 0
         };
 
-        Ok(MinecraftVersion { minor, patch })
+        Ok(MinecraftVersion::Release { minor, patch })
+    }
+
+    /// Parses `source`, and if it has no patch version (`1.19` or `1.19.x`),
+    /// picks the newest patch of that series in `supported` (`1.19.4`). The
+    /// version is used as is if `supported` has no version of that series
+    pub fn parse_latest_patch(
+        source: &str,
+        supported: &[MinecraftVersion],
+    ) -> Result<Self, VersionError> {
+        if !Self::is_series(source) {
+            return Self::parse(source);
+        }
+
+        let version = Self::parse(source.strip_suffix(".x").unwrap_or(source))?;
+        let MinecraftVersion::Release { minor, .. } = version else {
+            return Ok(version);
+        };
+
+        Ok(supported
+            .iter()
+            .filter(|v| {
+                matches!(v, MinecraftVersion::Release { minor: m, .. } if *m == minor)
+            })
+            .max()
+            .cloned()
+            .unwrap_or(version))
+    }
+
+    /// Whether `source` names a series of releases (`1.19`, `1.19.x`) rather
+    /// than a single version
+    pub fn is_series(source: &str) -> bool {
+        source.ends_with(".x") || source.split('.').count() == 2
+    }
+}
+
+/// Constraints on Fabric or Quilt loader versions, like `>=0.14, <0.15`. A
+/// version without an operator has to match exactly
+#[derive(Debug, Clone)]
+pub struct LoaderVersionRange {
+    constraints: Vec<(Operator, Vec<u32>)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl LoaderVersionRange {
+    pub fn parse(source: &str) -> Result<Self, VersionError> {
+        // longer operators first, so that `>=` isn't read as `>`
+        const OPERATORS: [(&str, Operator); 5] = [
+            (">=", Operator::Ge),
+            ("<=", Operator::Le),
+            (">", Operator::Gt),
+            ("<", Operator::Lt),
+            ("=", Operator::Eq),
+        ];
+
+        let constraints = source
+            .split(',')
+            .map(|constraint| {
+                let constraint = constraint.trim();
+                let (operator, version) = OPERATORS
+                    .iter()
+                    .find_map(|(prefix, operator)| {
+                        constraint.strip_prefix(prefix).map(|v| (*operator, v))
+                    })
+                    .unwrap_or((Operator::Eq, constraint));
+                let parts = loader_version_parts(version.trim())
+                    .ok_or(VersionError::InvalidVersion("loader"))?;
+                Ok((operator, parts))
+            })
+            .collect::<Result<_, VersionError>>()?;
+
+        Ok(Self { constraints })
+    }
+
+    /// Whether `version` satisfies every constraint
+    pub fn matches(&self, version: &str) -> bool {
+        let Some(parts) = loader_version_parts(version) else {
+            return false;
+        };
+
+        self.constraints.iter().all(|(operator, bound)| {
+            let ordering = compare_loader_versions(&parts, bound);
+            match operator {
+                Operator::Lt => ordering.is_lt(),
+                Operator::Le => ordering.is_le(),
+                Operator::Eq => ordering.is_eq(),
+                Operator::Ge => ordering.is_ge(),
+                Operator::Gt => ordering.is_gt(),
+            }
+        })
+    }
+}
+
+/// The numbers of a loader version, without its pre-release or build
+/// metadata: `0.14.21+build.3` is `[0, 14, 21]`
+fn loader_version_parts(version: &str) -> Option<Vec<u32>> {
+    let core = version.split(['-', '+']).next()?;
+    core.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Compares loader versions number by number, missing numbers counting as 0
+/// so that `0.14` is the same as `0.14.0`
+fn compare_loader_versions(a: &[u32], b: &[u32]) -> Ordering {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+impl MinecraftVersion {
+    /// Fetches every release Mojang knows about
+    pub async fn fetch_known() -> crate::Result<Vec<MinecraftVersion>> {
+        let manifest: VersionManifest = CLIENT
+            .get(VERSION_MANIFEST_URL)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        Ok(manifest
+            .versions
+            .into_iter()
+            .filter(|v| v.kind == "release")
+            .filter_map(|v| Self::parse(&v.id).ok())
+            .collect())
+    }
+
+    /// Whether this version is one of the `known` ones
+    pub fn is_valid(&self, known: &[MinecraftVersion]) -> bool {
+        known.contains(self)
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct VersionManifest {
+    versions: Vec<ManifestVersion>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestVersion {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
 impl Display for MinecraftVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.patch != 0 {
-            write!(f, "1.{}.{}", self.minor, self.patch)
-        } else {
-            write!(f, "1.{}", self.minor)
+        match self {
+            MinecraftVersion::Release { minor, patch: 0 } => {
+                write!(f, "1.{minor}")
+            }
+            MinecraftVersion::Release { minor, patch } => {
+                write!(f, "1.{minor}.{patch}")
+            }
+            MinecraftVersion::Snapshot {
+                year,
+                week,
+                revision,
+            } => write!(f, "{year:02}w{week:02}{revision}"),
         }
     }
 }
 
-impl PartialEq for MinecraftVersion {
-    fn eq(&self, other: &Self) -> bool {
-        self.minor == other.minor && self.patch == other.patch
+impl FromStr for MinecraftVersion {
+    type Err = VersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
     }
 }
 
-impl PartialOrd for MinecraftVersion {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.minor.partial_cmp(&other.minor) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
+impl Serialize for MinecraftVersion {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MinecraftVersion {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Self::parse(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn supported() -> Vec<MinecraftVersion> {
+        ["1.18.2", "1.19", "1.19.2", "1.19.4", "1.20.1"]
+            .iter()
+            .map(|v| MinecraftVersion::parse(v).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn latest_patch_of_a_series() {
+        let latest = MinecraftVersion::new(19, 4);
+        for source in ["1.19", "1.19.x"] {
+            let version =
+                MinecraftVersion::parse_latest_patch(source, &supported());
+            assert_eq!(version.unwrap(), latest);
         }
-        self.patch.partial_cmp(&other.patch)
+    }
+
+    #[test]
+    fn latest_patch_keeps_exact_versions() {
+        let version =
+            MinecraftVersion::parse_latest_patch("1.19.2", &supported());
+        assert_eq!(version.unwrap(), MinecraftVersion::new(19, 2));
+    }
+
+    #[test]
+    fn latest_patch_of_an_unsupported_series() {
+        let version =
+            MinecraftVersion::parse_latest_patch("1.17.x", &supported());
+        assert_eq!(version.unwrap(), MinecraftVersion::new(17, 0));
+        let version = MinecraftVersion::parse_latest_patch("1.20", &[]);
+        assert_eq!(version.unwrap(), MinecraftVersion::new(20, 0));
+    }
+
+    #[test]
+    fn latest_patch_rejects_invalid_versions() {
+        assert!(
+            MinecraftVersion::parse_latest_patch("1.x", &supported()).is_err()
+        );
+        assert!(
+            MinecraftVersion::parse_latest_patch("2.19", &supported()).is_err()
+        );
+    }
+
+    #[test]
+    fn loader_versions_compare_as_numbers() {
+        let range = LoaderVersionRange::parse(">=0.14.9").unwrap();
+        assert!(range.matches("0.14.21"));
+        assert!(range.matches("0.14.9"));
+        assert!(!range.matches("0.14.8"));
+        let range = LoaderVersionRange::parse("<0.14.10").unwrap();
+        assert!(range.matches("0.14.9"));
+        assert!(!range.matches("0.14.21"));
+    }
+
+    #[test]
+    fn loader_version_range_boundaries() {
+        let range = LoaderVersionRange::parse(">=0.14, <0.15").unwrap();
+        assert!(range.matches("0.14"));
+        assert!(range.matches("0.14.0"));
+        assert!(range.matches("0.14.25+build.1"));
+        assert!(!range.matches("0.13.3"));
+        assert!(!range.matches("0.15.0"));
+        let range = LoaderVersionRange::parse(">0.14.9, <=0.14.21").unwrap();
+        assert!(!range.matches("0.14.9"));
+        assert!(range.matches("0.14.10"));
+        assert!(range.matches("0.14.21"));
+        assert!(!range.matches("0.14.22"));
+    }
+
+    #[test]
+    fn loader_version_without_operator_matches_exactly() {
+        let range = LoaderVersionRange::parse("0.14.21").unwrap();
+        assert!(range.matches("0.14.21"));
+        assert!(range.matches("0.14.21-beta.1"));
+        assert!(!range.matches("0.14.2"));
+        assert!(LoaderVersionRange::parse(">=0.14.x").is_err());
     }
 }